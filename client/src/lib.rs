@@ -0,0 +1,391 @@
+//! Client for the SpamWatch API.
+//!
+//! Requests are sent with `awc`, so the futures have to run on an actix system:
+//!
+//! ```no_run
+//! use spamwatch_client::Client;
+//!
+//! let mut system = actix_rt::System::new("spamwatch");
+//! let client = Client::new("https://api.spamwat.ch", "TOKEN");
+//! match system.block_on(client.ban(777000)).unwrap() {
+//!     Some(ban) => println!("Banned for {}", ban.reason),
+//!     None => println!("Not banned"),
+//! }
+//! ```
+//!
+//! Rate limited requests are retried once the API allows them again, see `Client::max_retries`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use awc::error::{PayloadError, SendRequestError};
+use awc::http::{Method, StatusCode};
+use futures::future::{err, lazy, loop_fn, ok, Loop};
+use futures::Future;
+use serde::de::DeserializeOwned;
+use tokio_timer::Delay;
+
+pub use spamwatch_types::*;
+
+/// `/banlist` and `/banlist/all` grow with the banlist, so the default limit of awc is too small
+const MAX_BODY_SIZE: usize = 256 * 1024 * 1024;
+
+pub type Response<T> = Box<dyn Future<Item=T, Error=ClientError>>;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The API answered with an error
+    Api(Error),
+    /// Still rate limited after `max_retries` retries, or waiting would take longer than `max_wait`
+    RateLimited { until: i64 },
+    /// The API answered with an error that isn't JSON, e.g. from a proxy in front of it
+    Status(u16, String),
+    Send(SendRequestError),
+    Payload(PayloadError),
+    Json(serde_json::Error),
+    Query(serde_urlencoded::ser::Error),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Api(e) => match &e.reason {
+                Some(reason) => write!(f, "{} {}: {}", e.code, e.error, reason),
+                None => write!(f, "{} {}", e.code, e.error),
+            },
+            ClientError::RateLimited { until } => write!(f, "Rate limited until {}", until),
+            ClientError::Status(code, body) => write!(f, "{}: {}", code, body),
+            ClientError::Send(e) => write!(f, "{}", e),
+            ClientError::Payload(e) => write!(f, "{}", e),
+            ClientError::Json(e) => write!(f, "{}", e),
+            ClientError::Query(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl ClientError {
+    /// HTTP status code of errors returned by the API.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ClientError::Api(e) => Some(e.code),
+            ClientError::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS.as_u16()),
+            ClientError::Status(code, _) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: awc::Client,
+    host: String,
+    token: String,
+    /// How often a rate limited request is retried once the API allows it again
+    pub max_retries: u32,
+    /// Rate limited requests that would have to wait longer fail right away
+    pub max_wait: Duration,
+}
+
+impl Client {
+    /// `host` is the URL of the API without a trailing slash, e.g. `https://api.spamwat.ch`.
+    pub fn new(host: impl Into<String>, token: impl Into<String>) -> Self {
+        Client {
+            http: awc::Client::default(),
+            host: host.into(),
+            token: token.into(),
+            max_retries: 3,
+            max_wait: Duration::from_secs(60),
+        }
+    }
+
+    /// Time to wait until `until`, `None` if that's longer than `max_wait`.
+    fn wait_for(&self, until: i64) -> Option<Duration> {
+        let until = UNIX_EPOCH + Duration::from_secs(until.max(0) as u64);
+        let wait = until.duration_since(SystemTime::now()).unwrap_or_default();
+        if wait <= self.max_wait {
+            Some(wait)
+        } else {
+            None
+        }
+    }
+
+    fn attempt(&self, method: &Method, path: &str, body: Option<Vec<u8>>) -> Response<(StatusCode, Vec<u8>)> {
+        let request = self.http
+            .request(method.clone(), format!("{}{}", self.host, path))
+            .bearer_auth(&self.token);
+        let sent = match body {
+            Some(body) => request.content_type("application/json").send_body(body),
+            None => request.send(),
+        };
+        Box::new(sent.map_err(ClientError::Send).and_then(|mut response| {
+            let status = response.status();
+            response.body().limit(MAX_BODY_SIZE).map_err(ClientError::Payload).and_then(move |body| {
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    if let Ok(Error { until: Some(until), .. }) = serde_json::from_slice(&body) {
+                        return Err(ClientError::RateLimited { until });
+                    }
+                }
+                Ok((status, body.to_vec()))
+            })
+        }))
+    }
+
+    /// Sends the request, waiting and retrying while it's rate limited.
+    fn send(&self, method: Method, path: String, body: Option<Vec<u8>>) -> Response<(StatusCode, Vec<u8>)> {
+        let client = self.clone();
+        // awc starts connecting right away, which only works inside a task
+        Box::new(lazy(move || loop_fn(0, move |retries| {
+            let client_ = client.clone();
+            client.attempt(&method, &path, body.clone()).then(move |result| -> Response<Loop<_, u32>> {
+                match result {
+                    Err(ClientError::RateLimited { until }) if retries < client_.max_retries => {
+                        match client_.wait_for(until) {
+                            // A failing timer only means retrying early
+                            Some(wait) => Box::new(Delay::new(Instant::now() + wait)
+                                .then(move |_| Ok(Loop::Continue(retries + 1)))),
+                            None => Box::new(err(ClientError::RateLimited { until })),
+                        }
+                    }
+                    Ok(response) => Box::new(ok(Loop::Break(response))),
+                    Err(e) => Box::new(err(e)),
+                }
+            })
+        })))
+    }
+
+    fn send_json<B: serde::Serialize>(&self, method: Method, path: String, body: &B) -> Response<(StatusCode, Vec<u8>)> {
+        match serde_json::to_vec(body) {
+            Ok(body) => self.send(method, path, Some(body)),
+            Err(e) => Box::new(err(ClientError::Json(e))),
+        }
+    }
+
+    fn get<T: DeserializeOwned + 'static>(&self, path: String) -> Response<T> {
+        Box::new(self.send(Method::GET, path, None).and_then(parse))
+    }
+
+    fn get_text(&self, path: &str) -> Response<String> {
+        Box::new(self.send(Method::GET, path.to_string(), None).and_then(text))
+    }
+
+    fn no_content(response: Response<(StatusCode, Vec<u8>)>) -> Response<()> {
+        Box::new(response.and_then(|(status, body)| check(status, body).map(|_| ())))
+    }
+
+    //region Root
+    /// HTML page with information about the instance.
+    pub fn info(&self) -> Response<String> {
+        self.get_text("/")
+    }
+
+    pub fn version(&self) -> Response<Version> {
+        self.get("/version".to_string())
+    }
+
+    pub fn health(&self) -> Response<Health> {
+        self.get("/health".to_string())
+    }
+
+    /// Also returns the checks if the API isn't ready.
+    pub fn ready(&self) -> Response<Ready> {
+        Box::new(self.send(Method::GET, "/ready".to_string(), None).and_then(|(status, body)| {
+            if status == StatusCode::SERVICE_UNAVAILABLE {
+                serde_json::from_slice(&body).map_err(ClientError::Json)
+            } else {
+                parse((status, body))
+            }
+        }))
+    }
+
+    pub fn stats(&self) -> Response<Stats> {
+        self.get("/stats".to_string())
+    }
+
+    /// OpenAPI 3 document of the API.
+    pub fn openapi(&self) -> Response<serde_json::Value> {
+        self.get("/openapi.json".to_string())
+    }
+
+    /// Prometheus metrics, if they are served on the API address.
+    pub fn metrics(&self) -> Response<String> {
+        self.get_text("/metrics")
+    }
+    //endregion
+
+    //region Tokens
+    pub fn tokens(&self) -> Response<Vec<Token>> {
+        self.get("/tokens".to_string())
+    }
+
+    pub fn create_token(&self, token: &CreateToken) -> Response<CreatedToken> {
+        Box::new(self.send_json(Method::POST, "/tokens".to_string(), token).and_then(parse))
+    }
+
+    /// Tokens neither used nor created since `since`, 30 days ago by default.
+    pub fn inactive_tokens(&self, since: Option<i64>) -> Response<Vec<Token>> {
+        match since {
+            Some(since) => self.get(format!("/tokens/inactive?since={}", since)),
+            None => self.get("/tokens/inactive".to_string()),
+        }
+    }
+
+    pub fn token(&self, id: i32) -> Response<TokenDetails> {
+        self.get(format!("/tokens/{}", id))
+    }
+
+    /// The token this client uses.
+    pub fn own_token(&self) -> Response<TokenDetails> {
+        self.get("/tokens/self".to_string())
+    }
+
+    pub fn revoke_token(&self, id: i32) -> Response<()> {
+        Self::no_content(self.send(Method::DELETE, format!("/tokens/{}", id), None))
+    }
+
+    pub fn tokens_by_userid(&self, userid: i64) -> Response<Vec<Token>> {
+        self.get(format!("/tokens/userid/{}", userid))
+    }
+
+    pub fn token_limits(&self, id: i32) -> Response<TokenLimits> {
+        self.get(format!("/tokens/{}/limits", id))
+    }
+
+    /// Replaces the overrides of the token. Limits that are left out use the config again.
+    pub fn set_token_limits(&self, id: i32, limits: &BTreeMap<String, Limit>) -> Response<()> {
+        Self::no_content(self.send_json(Method::PUT, format!("/tokens/{}/limits", id), limits))
+    }
+
+    /// Refills all buckets of the token and removes its overrides.
+    pub fn reset_token_limits(&self, id: i32) -> Response<()> {
+        Self::no_content(self.send(Method::DELETE, format!("/tokens/{}/limits", id), None))
+    }
+    //endregion
+
+    //region Banlist
+    /// The whole banlist. Requires Root, see `ban_page` for browsing it.
+    pub fn bans(&self) -> Response<Vec<Ban>> {
+        self.get("/banlist".to_string())
+    }
+
+    /// A page of bans matching the query. Pass its `cursor` as `after` to get the next one.
+    /// Requires Root like `bans`.
+    /// `limit` defaults to 100, as a query without any field set would export the whole banlist.
+    pub fn ban_page(&self, query: &BanListQuery) -> Response<BanPage> {
+        let query = BanListQuery { limit: Some(query.limit.unwrap_or(100)), ..query.clone() };
+        match serde_urlencoded::to_string(&query) {
+            Ok(query) => self.get(format!("/banlist?{}", query)),
+            Err(e) => Box::new(err(ClientError::Query(e))),
+        }
+    }
+
+    /// Adds the bans, or updates them if the users are banned already. If one of them is invalid,
+    /// none are added.
+    pub fn add_bans(&self, bans: &[CreateBan]) -> Response<()> {
+        Self::no_content(self.send_json(Method::POST, "/banlist".to_string(), &bans))
+    }
+
+    /// Adds the valid bans and skips the others, instead of failing if any of them is invalid.
+    pub fn add_bans_partial(&self, bans: &[CreateBan]) -> Response<Vec<BanResult>> {
+        Box::new(self.send_json(Method::POST, "/banlist?partial=true".to_string(), &bans).and_then(parse))
+    }
+
+    pub fn banned_ids(&self) -> Response<Vec<i64>> {
+        Box::new(self.get_text("/banlist/all").and_then(|ids| {
+            ids.lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.parse().map_err(|_| ClientError::Status(200, format!("invalid user id `{}`", line))))
+                .collect::<Result<Vec<i64>, _>>()
+        }))
+    }
+
+    pub fn ban_changes_since(&self, since: i64) -> Response<BanChanges> {
+        self.get(format!("/banlist/changes?since={}", since))
+    }
+
+    /// Changes after the ones that returned `cursor`.
+    pub fn ban_changes_after(&self, cursor: &str) -> Response<BanChanges> {
+        self.get(format!("/banlist/changes?cursor={}", cursor))
+    }
+
+    /// Full-text search over the reasons and messages of bans, best matches first.
+    pub fn search_bans(&self, search: &str, limit: Option<u32>) -> Response<Vec<BanMatch>> {
+        let mut query = vec![("q", search.to_string())];
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()));
+        }
+        match serde_urlencoded::to_string(&query) {
+            Ok(query) => self.get(format!("/banlist/search?{}", query)),
+            Err(e) => Box::new(err(ClientError::Query(e))),
+        }
+    }
+
+    /// Bans of the users that are banned.
+    pub fn check_bans(&self, user_ids: &[i64]) -> Response<Vec<Ban>> {
+        Box::new(self.send_json(Method::POST, "/banlist/check".to_string(), &user_ids).and_then(parse))
+    }
+
+    /// `None` if the user isn't banned.
+    pub fn ban(&self, user_id: i64) -> Response<Option<Ban>> {
+        Box::new(self.send(Method::GET, format!("/banlist/{}", user_id), None).and_then(|(status, body)| {
+            if status == StatusCode::NOT_FOUND {
+                Ok(None)
+            } else {
+                parse((status, body)).map(Some)
+            }
+        }))
+    }
+
+    pub fn delete_ban(&self, user_id: i64) -> Response<()> {
+        Self::no_content(self.send(Method::DELETE, format!("/banlist/{}", user_id), None))
+    }
+
+    /// Every change to the ban, oldest first.
+    pub fn ban_history(&self, user_id: i64) -> Response<Vec<BanHistory>> {
+        self.get(format!("/banlist/{}/history", user_id))
+    }
+    //endregion
+
+    //region Ban categories
+    pub fn ban_categories(&self) -> Response<Vec<BanCategory>> {
+        self.get("/banlist/categories".to_string())
+    }
+
+    pub fn create_ban_category(&self, category: &CreateBanCategory) -> Response<BanCategory> {
+        Box::new(self.send_json(Method::POST, "/banlist/categories".to_string(), category).and_then(parse))
+    }
+
+    /// Replaces the name and description of the category.
+    pub fn update_ban_category(&self, id: i32, category: &CreateBanCategory) -> Response<BanCategory> {
+        Box::new(self.send_json(Method::PUT, format!("/banlist/categories/{}", id), category).and_then(parse))
+    }
+
+    /// Fails with `409 Conflict` while any ban has the category.
+    pub fn delete_ban_category(&self, id: i32) -> Response<()> {
+        Self::no_content(self.send(Method::DELETE, format!("/banlist/categories/{}", id), None))
+    }
+    //endregion
+}
+
+/// Turns error responses into errors.
+fn check(status: StatusCode, body: Vec<u8>) -> Result<Vec<u8>, ClientError> {
+    if status.is_success() {
+        return Ok(body);
+    }
+    match serde_json::from_slice(&body) {
+        Ok(error) => Err(ClientError::Api(error)),
+        Err(_) => Err(ClientError::Status(status.as_u16(), String::from_utf8_lossy(&body).into_owned())),
+    }
+}
+
+fn parse<T: DeserializeOwned>((status, body): (StatusCode, Vec<u8>)) -> Result<T, ClientError> {
+    let body = check(status, body)?;
+    serde_json::from_slice(&body).map_err(ClientError::Json)
+}
+
+fn text((status, body): (StatusCode, Vec<u8>)) -> Result<String, ClientError> {
+    let body = check(status, body)?;
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
@@ -1 +1 @@
-ALTER TABLE banlist ADD COLUMN message text;
+ALTER TABLE banlist ADD COLUMN IF NOT EXISTS message text;
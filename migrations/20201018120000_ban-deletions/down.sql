@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS banlist_date_idx;
+
+DROP TABLE banlist_deletions;
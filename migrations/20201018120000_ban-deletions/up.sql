@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS banlist_deletions
+(
+    id   bigint    NOT NULL PRIMARY KEY,
+    date timestamp NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS banlist_date_idx ON banlist (date);
+CREATE INDEX IF NOT EXISTS banlist_deletions_date_idx ON banlist_deletions (date);
@@ -0,0 +1,3 @@
+DROP TABLE ban_history;
+
+DROP TYPE ban_action;
@@ -0,0 +1,22 @@
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_type WHERE typname = 'ban_action') THEN
+        CREATE TYPE ban_action AS ENUM ('Create', 'Update', 'Delete');
+    END IF;
+
+END$$;
+
+CREATE TABLE IF NOT EXISTS ban_history
+(
+    id          SERIAL PRIMARY KEY,
+    ban_id      bigint                         NOT NULL,
+    action      ban_action                     NOT NULL,
+    token       integer references tokens (id),
+    old_reason  Text,
+    new_reason  Text,
+    old_message Text,
+    new_message Text,
+    date        timestamp                      NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS ban_history_ban_id_idx ON ban_history (ban_id);
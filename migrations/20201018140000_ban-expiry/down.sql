@@ -0,0 +1,4 @@
+-- Values can't be removed from an enum, so `Expire` stays in `ban_action`
+DROP INDEX IF EXISTS banlist_expires_idx;
+
+ALTER TABLE banlist DROP COLUMN IF EXISTS expires;
@@ -0,0 +1,6 @@
+ALTER TABLE banlist ADD COLUMN IF NOT EXISTS expires timestamp;
+
+CREATE INDEX IF NOT EXISTS banlist_expires_idx ON banlist (expires) WHERE expires IS NOT NULL;
+
+-- Requires PostgreSQL 12 or newer when run inside a transaction
+ALTER TYPE ban_action ADD VALUE IF NOT EXISTS 'Expire';
@@ -0,0 +1,4 @@
+-- Hashes can't be turned back into secrets. Every token has to be reissued after rolling this back.
+DROP INDEX IF EXISTS tokens_token_hash_idx;
+
+ALTER TABLE tokens RENAME COLUMN token_hash TO token;
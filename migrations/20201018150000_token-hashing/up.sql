@@ -0,0 +1,6 @@
+-- `sha256()` requires PostgreSQL 11 or newer
+ALTER TABLE tokens RENAME COLUMN token TO token_hash;
+
+UPDATE tokens SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
+
+CREATE UNIQUE INDEX IF NOT EXISTS tokens_token_hash_idx ON tokens (token_hash);
@@ -0,0 +1,4 @@
+ALTER TABLE tokens DROP COLUMN IF EXISTS created_by;
+ALTER TABLE tokens DROP COLUMN IF EXISTS created_at;
+ALTER TABLE tokens DROP COLUMN IF EXISTS description;
+ALTER TABLE tokens DROP COLUMN IF EXISTS name;
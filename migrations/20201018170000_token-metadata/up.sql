@@ -0,0 +1,6 @@
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS name varchar(64);
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS description text;
+-- Tokens created before this migration have no known creation date
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS created_at timestamp;
+ALTER TABLE tokens ALTER COLUMN created_at SET DEFAULT now();
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS created_by integer REFERENCES tokens (id);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS token_usage;
+ALTER TABLE tokens DROP COLUMN IF EXISTS last_used_at;
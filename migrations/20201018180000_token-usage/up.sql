@@ -0,0 +1,10 @@
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS last_used_at timestamp;
+
+CREATE TABLE IF NOT EXISTS token_usage
+(
+    token_id     integer references tokens (id) NOT NULL,
+    endpoint     Text                           NOT NULL,
+    requests     bigint                         NOT NULL,
+    last_used_at timestamp                      NOT NULL,
+    PRIMARY KEY (token_id, endpoint)
+);
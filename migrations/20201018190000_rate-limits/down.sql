@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS antiflood;
+CREATE TABLE IF NOT EXISTS antiflood
+(
+    token       integer references tokens (id) NOT NULL PRIMARY KEY,
+    banlist_all timestamp                      NOT NULL
+);
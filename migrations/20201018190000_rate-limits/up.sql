@@ -0,0 +1,10 @@
+-- One token bucket per token and endpoint replaces the fixed `banlist_all` cooldown
+DROP TABLE IF EXISTS antiflood;
+CREATE TABLE IF NOT EXISTS antiflood
+(
+    token      integer references tokens (id) NOT NULL,
+    endpoint   Text                           NOT NULL,
+    available  double precision               NOT NULL,
+    updated_at timestamp                      NOT NULL,
+    PRIMARY KEY (token, endpoint)
+);
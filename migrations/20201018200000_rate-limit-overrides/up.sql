@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS antiflood_overrides
+(
+    token  integer references tokens (id) NOT NULL,
+    name   Text                           NOT NULL,
+    burst  bigint                         NOT NULL,
+    period bigint                         NOT NULL,
+    PRIMARY KEY (token, name)
+);
@@ -0,0 +1,5 @@
+DROP FUNCTION IF EXISTS escape_html(text);
+
+DROP INDEX IF EXISTS banlist_search_idx;
+
+ALTER TABLE banlist DROP COLUMN IF EXISTS search;
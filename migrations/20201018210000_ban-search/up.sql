@@ -0,0 +1,15 @@
+-- The `simple` configuration doesn't stem, so it works the same for every language bans are written in.
+-- Words of the reason rank higher than words of the message
+ALTER TABLE banlist
+    ADD COLUMN IF NOT EXISTS search tsvector GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', reason), 'A') ||
+        setweight(to_tsvector('simple', coalesce(message, '')), 'B')
+        ) STORED;
+
+CREATE INDEX IF NOT EXISTS banlist_search_idx ON banlist USING gin (search);
+
+-- Snippets are highlighted in HTML-escaped text, so the highlighting tags are the only markup in them
+CREATE OR REPLACE FUNCTION escape_html(text) RETURNS text AS
+$$
+SELECT replace(replace(replace(replace($1, '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;')
+$$ LANGUAGE sql IMMUTABLE;
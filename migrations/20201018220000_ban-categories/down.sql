@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS banlist_category_idx;
+
+ALTER TABLE banlist DROP COLUMN IF EXISTS category;
+
+DROP TABLE IF EXISTS ban_categories;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS ban_categories
+(
+    id          serial PRIMARY KEY,
+    name        Text NOT NULL UNIQUE,
+    description Text
+);
+
+-- `reason` stays as free-text detail, existing bans are uncategorized
+ALTER TABLE banlist ADD COLUMN IF NOT EXISTS category integer references ban_categories (id);
+
+CREATE INDEX IF NOT EXISTS banlist_category_idx ON banlist (category) WHERE category IS NOT NULL;
@@ -0,0 +1,3 @@
+-- Route keys can't be turned back into the paths they were merged from, and the old keys are only
+-- statistics and rate limit state, so they are kept as they are.
+SELECT 1;
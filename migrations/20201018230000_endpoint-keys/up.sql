@@ -0,0 +1,41 @@
+-- Usage and rate limit buckets are keyed by the route pattern, e.g. `GET /tokens/{id}`. Keys
+-- written before replaced only numeric path segments, e.g. `GET /tokens/self` or
+-- `GET /tokens/userid/{id}`, so they are rewritten and rows that end up with the same key merged.
+-- Paths with percent-encoded fixed segments, e.g. `/banlist/%61ll`, can't be told apart from IDs
+-- here and are counted under `/banlist/{id}`.
+CREATE FUNCTION endpoint_route(endpoint Text) RETURNS Text AS
+$$
+SELECT split_part(endpoint, ' ', 1) || ' ' || CASE
+    WHEN path ~ '^/banlist/(all|changes|check|search|categories)$' THEN path
+    WHEN path ~ '^/banlist/categories/[^/]+$' THEN '/banlist/categories/{id}'
+    WHEN path ~ '^/banlist/[^/]+/history$' THEN '/banlist/{id}/history'
+    WHEN path ~ '^/banlist/[^/]+$' THEN '/banlist/{id}'
+    WHEN path ~ '^/tokens/inactive$' THEN path
+    WHEN path ~ '^/tokens/userid/[^/]+$' THEN '/tokens/userid/{uid}'
+    WHEN path ~ '^/tokens/[^/]+/limits$' THEN '/tokens/{id}/limits'
+    WHEN path ~ '^/tokens/[^/]+$' THEN '/tokens/{id}'
+    ELSE path
+    END
+FROM (SELECT substr(endpoint, strpos(endpoint, ' ') + 1) AS path) AS p;
+$$ LANGUAGE SQL IMMUTABLE;
+
+CREATE TEMPORARY TABLE token_usage_routes ON COMMIT DROP AS
+SELECT token_id, endpoint_route(endpoint) AS endpoint, sum(requests)::bigint AS requests,
+       max(last_used_at) AS last_used_at
+FROM token_usage
+GROUP BY 1, 2;
+DELETE FROM token_usage;
+INSERT INTO token_usage (token_id, endpoint, requests, last_used_at)
+SELECT token_id, endpoint, requests, last_used_at FROM token_usage_routes;
+
+-- The emptiest bucket wins, so merging never grants extra requests
+CREATE TEMPORARY TABLE antiflood_routes ON COMMIT DROP AS
+SELECT token, endpoint_route(endpoint) AS endpoint, min(available) AS available,
+       max(updated_at) AS updated_at
+FROM antiflood
+GROUP BY 1, 2;
+DELETE FROM antiflood;
+INSERT INTO antiflood (token, endpoint, available, updated_at)
+SELECT token, endpoint, available, updated_at FROM antiflood_routes;
+
+DROP FUNCTION endpoint_route(Text);
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS banlist_deletions_txid_idx;
+DROP INDEX IF EXISTS banlist_txid_idx;
+
+ALTER TABLE banlist_deletions DROP COLUMN IF EXISTS txid;
+ALTER TABLE banlist DROP COLUMN IF EXISTS txid;
@@ -0,0 +1,9 @@
+-- ID of the transaction that last changed the row. Delta syncs continue from the oldest transaction
+-- that was still running when the previous one read, so no commit is skipped however long it took.
+-- A sequence would be no better than `now()`: its values are taken in the order transactions write,
+-- not in the order they commit.
+ALTER TABLE banlist ADD COLUMN IF NOT EXISTS txid bigint NOT NULL DEFAULT txid_current();
+ALTER TABLE banlist_deletions ADD COLUMN IF NOT EXISTS txid bigint NOT NULL DEFAULT txid_current();
+
+CREATE INDEX IF NOT EXISTS banlist_txid_idx ON banlist (txid);
+CREATE INDEX IF NOT EXISTS banlist_deletions_txid_idx ON banlist_deletions (txid);
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use spamwatch_types as api;
+
+use crate::database::{self, migrations, NewBan, NewToken, Pool, Storage, Token};
+use crate::guards::Permission;
+use crate::settings;
+use crate::utils;
+
+const USAGE: &str = "Usage: SpamWatchAPI [command]
+
+Without a command the API server is started.
+
+Commands:
+    migrate             Apply all pending migrations
+    rollback [steps]    Revert the last `steps` migrations (default: 1)
+    migrations          List all migrations and whether they are applied
+    stats               Show the number of bans and active tokens
+
+    tokens list
+    tokens create <permission> <userid> [--name <name>] [--description <text>] [--expires <timestamp>]
+    tokens revoke <id>
+    tokens rotate <id>          Replace the secret of a token, keeping its ID
+    tokens reissue-genesis      Replace the secret of the Genesis Token, or create it if it's missing
+
+    bans get <user id>
+    bans add <user id> <reason> [--category <id>] [--message <text>] [--expires <timestamp>]
+    bans remove <user id>
+
+Bans are added and removed in the name of the Genesis Token. Timestamps are Unix timestamps.";
+
+/// Token that changes to the banlist made on the command line are attributed to
+const GENESIS_TOKEN: i32 = 1;
+
+/// Runs the command given on the command line and returns the exit code.
+pub fn run(pool: &Pool, args: &[String]) -> Result<i32, database::Error> {
+    let mut db = pool.get()?;
+    let command = args.first().map(String::as_str);
+    // Everything but the migration commands needs the schema this binary was built for
+    if !matches!(command, Some("migrate") | Some("rollback") | Some("migrations")) {
+        let pending = migrations::pending(&mut *db)?.len();
+        if pending > 0 {
+            migrations::log_out_of_date(pending);
+            return Ok(1);
+        }
+    }
+    let code = match command {
+        Some("migrate") => {
+            let applied = migrations::run_pending(&mut *db)?;
+            info!(utils::LOGGER, "Applied {} migration(s)", applied);
+            0
+        }
+        Some("rollback") => {
+            let steps = match args.get(1).map(|s| s.parse::<usize>()) {
+                Some(Ok(steps)) => steps,
+                Some(Err(_)) => return Ok(usage()),
+                None => 1,
+            };
+            let reverted = migrations::rollback(&mut *db, steps)?;
+            info!(utils::LOGGER, "Reverted {} migration(s)", reverted);
+            0
+        }
+        Some("migrations") => {
+            let applied = db.applied_migrations()?;
+            for migration in migrations::MIGRATIONS {
+                let state = if applied.iter().any(|v| v == migration.version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!("{} {:<20} {}", migration.version, migration.name, state);
+            }
+            0
+        }
+        Some("stats") => {
+            println!("Bans:          {}", db.get_total_ban_count()?);
+            println!("Active tokens: {}", db.get_active_token_count()?);
+            let counts = db.get_ban_category_counts()?;
+            if !counts.is_empty() {
+                println!("Bans by category:");
+            }
+            for (category, count) in counts {
+                println!("    {:<20} {}", category.name, count);
+            }
+            0
+        }
+        Some("tokens") => tokens(&mut *db, &args[1..])?,
+        Some("bans") => bans(&mut *db, &args[1..])?,
+        _ => usage(),
+    };
+    Ok(code)
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+/// Rejects arguments the API would reject as well.
+fn invalid(error: &str) -> i32 {
+    eprintln!("Invalid arguments: {}", error);
+    2
+}
+
+fn tokens(db: &mut dyn Storage, args: &[String]) -> Result<i32, database::Error> {
+    let (args, options) = match parse_options(args, &["name", "description", "expires"]) {
+        Some(parsed) => parsed,
+        None => return Ok(usage()),
+    };
+    match args.as_slice() {
+        ["list"] => {
+            for token in db.get_tokens()? {
+                print_token(&token);
+            }
+        }
+        ["create", permission, userid] => {
+            let permission = match parse_permission(permission) {
+                Some(permission) => permission,
+                None => return Ok(usage()),
+            };
+            let userid = match userid.parse() {
+                Ok(userid) => userid,
+                Err(_) => return Ok(usage()),
+            };
+            let mut token = api::CreateToken::new(userid, permission);
+            token.name = options.get("name").map(|name| name.to_string());
+            token.description = options.get("description").map(|description| description.to_string());
+            token.expires_at = match options.get("expires").map(|expires| expires.parse()) {
+                Some(Ok(expires)) => Some(expires),
+                Some(Err(_)) => return Ok(usage()),
+                None => None,
+            };
+            match NewToken::from_api(&token, Utc::now().timestamp()) {
+                Ok(new_token) => println!("{}", db.create_token(&new_token)?),
+                Err(error) => return Ok(invalid(error)),
+            }
+        }
+        ["revoke", id] | ["rotate", id] => {
+            let id = match id.parse() {
+                Ok(id) => id,
+                Err(_) => return Ok(usage()),
+            };
+            match db.get_token_by_id(id)? {
+                Some(token) if token.retired => {
+                    eprintln!("Token {} is retired already", id);
+                    return Ok(1);
+                }
+                Some(_) if args[0] == "revoke" => {
+                    db.revoke_token_by_id(id)?;
+                    info!(utils::LOGGER, "Revoked token"; "id" => id);
+                }
+                Some(_) => println!("{}", db.rotate_token(id)?),
+                None => {
+                    eprintln!("Token {} doesn't exist", id);
+                    return Ok(1);
+                }
+            }
+        }
+        ["reissue-genesis"] => match db.get_token_by_id(GENESIS_TOKEN)? {
+            Some(token) if token.retired => {
+                eprintln!("The Genesis Token was retired. Create a new root token with `tokens create Root <userid>`");
+                return Ok(1);
+            }
+            Some(_) => println!("{}", db.rotate_token(GENESIS_TOKEN)?),
+            None => {
+                let mut genesis = NewToken::new(Permission::Root, settings::ENV.general.masterid);
+                genesis.name = Some("Genesis Token".to_string());
+                println!("{}", db.create_token(&genesis)?);
+            }
+        },
+        _ => return Ok(usage()),
+    }
+    Ok(0)
+}
+
+fn bans(db: &mut dyn Storage, args: &[String]) -> Result<i32, database::Error> {
+    let (args, options) = match parse_options(args, &["category", "message", "expires"]) {
+        Some(parsed) => parsed,
+        None => return Ok(usage()),
+    };
+    let user_id: i64 = match args.get(1).map(|id| id.parse()) {
+        Some(Ok(user_id)) => user_id,
+        _ => return Ok(usage()),
+    };
+    match args.as_slice() {
+        ["get", _] => match db.get_ban(user_id)? {
+            Some(ban) => {
+                let category = match ban.category {
+                    Some(id) => db.get_ban_category(id)?.map_or(id.to_string(), |c| format!("{} ({})", c.name, id)),
+                    None => String::new(),
+                };
+                println!("User:     {}", ban.id);
+                println!("Reason:   {}", ban.reason);
+                println!("Category: {}", category);
+                println!("Date:     {}", ban.date);
+                println!("Admin:    {}", ban.admin);
+                println!("Message:  {}", ban.message.unwrap_or_default());
+                println!("Expires:  {}", ban.expires.map(|e| e.to_string()).unwrap_or_else(|| "never".to_string()));
+            }
+            None => {
+                eprintln!("User {} isn't banned", user_id);
+                return Ok(1);
+            }
+        },
+        ["add", _, reason] => {
+            let mut ban = api::CreateBan::new(user_id, *reason);
+            ban.message = options.get("message").map(|message| message.to_string());
+            ban.expires = match options.get("expires").map(|expires| expires.parse()) {
+                Some(Ok(expires)) => Some(expires),
+                Some(Err(_)) => return Ok(usage()),
+                None => None,
+            };
+            ban.category = match options.get("category").map(|category| category.parse()) {
+                Some(Ok(category)) => Some(category),
+                Some(Err(_)) => return Ok(usage()),
+                None => None,
+            };
+            let categories = if ban.category.is_some() { db.get_ban_categories()? } else { Vec::new() };
+            let ban = match NewBan::from_api(&ban, &categories, Utc::now().timestamp()) {
+                Ok(ban) => ban,
+                Err(error) => return Ok(invalid(error)),
+            };
+            db.add_bans(&[ban], GENESIS_TOKEN)?;
+            info!(utils::LOGGER, "Banned user"; "id" => user_id);
+        }
+        ["remove", _] => {
+            if db.get_ban(user_id)?.is_none() {
+                eprintln!("User {} isn't banned", user_id);
+                return Ok(1);
+            }
+            db.delete_ban(user_id, GENESIS_TOKEN)?;
+            info!(utils::LOGGER, "Unbanned user"; "id" => user_id);
+        }
+        _ => return Ok(usage()),
+    }
+    Ok(0)
+}
+
+/// Splits `--option value` pairs from the positional arguments. `None` for unknown options or missing values.
+fn parse_options<'a>(args: &'a [String], allowed: &[&str]) -> Option<(Vec<&'a str>, HashMap<&'a str, &'a str>)> {
+    let mut positional = Vec::new();
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) if allowed.contains(&name) => {
+                options.insert(name, args.next()?.as_str());
+            }
+            Some(_) => return None,
+            None => positional.push(arg.as_str()),
+        }
+    }
+    Some((positional, options))
+}
+
+fn parse_permission(permission: &str) -> Option<Permission> {
+    match permission.to_lowercase().as_str() {
+        "user" => Some(Permission::User),
+        "admin" => Some(Permission::Admin),
+        "root" => Some(Permission::Root),
+        _ => None,
+    }
+}
+
+fn print_token(token: &Token) {
+    let expires_at = match token.expires_at {
+        Some(expires_at) => expires_at.to_string(),
+        None => "never".to_string(),
+    };
+    println!("{:>5} {:<5} {:>12} {:<7} expires {:<19} {}",
+             token.id,
+             format!("{:?}", token.permission),
+             token.userid,
+             if token.retired { "retired" } else { "active" },
+             expires_at,
+             token.name.as_deref().unwrap_or(""));
+}
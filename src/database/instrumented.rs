@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+
+use crate::database::{Antiflood, Ban, BanAction, BanCategory, BanChanges, BanHistory, BanMatch, BanQuery, ChangesSince,
+                      Error, NewBan, NewToken, Storage, Token, TokenUsage};
+use crate::database::migrations::Migration;
+use crate::metrics;
+use crate::ratelimit::RateLimit;
+use crate::settings::Limit;
+
+/// Records how long each call into the wrapped storage backend takes.
+pub struct Instrumented<S>(pub S);
+
+impl<S: Storage> Storage for Instrumented<S> {
+    //region Migrations
+    fn applied_migrations(&mut self) -> Result<Vec<String>, Error> {
+        metrics::time_database("applied_migrations", || self.0.applied_migrations())
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), Error> {
+        metrics::time_database("apply_migration", || self.0.apply_migration(migration))
+    }
+
+    fn revert_migration(&mut self, migration: &Migration) -> Result<(), Error> {
+        metrics::time_database("revert_migration", || self.0.revert_migration(migration))
+    }
+
+    fn lock_migrations(&mut self) -> Result<(), Error> {
+        metrics::time_database("lock_migrations", || self.0.lock_migrations())
+    }
+
+    fn unlock_migrations(&mut self) -> Result<(), Error> {
+        metrics::time_database("unlock_migrations", || self.0.unlock_migrations())
+    }
+    //endregion
+
+    //region Tokens
+    fn get_tokens(&mut self) -> Result<Vec<Token>, Error> {
+        metrics::time_database("get_tokens", || self.0.get_tokens())
+    }
+
+    fn get_token_by_id(&mut self, token_id: i32) -> Result<Option<Token>, Error> {
+        metrics::time_database("get_token_by_id", || self.0.get_token_by_id(token_id))
+    }
+
+    fn get_token_by_userid(&mut self, userid: i64) -> Result<Vec<Token>, Error> {
+        metrics::time_database("get_token_by_userid", || self.0.get_token_by_userid(userid))
+    }
+
+    fn get_active_token_count(&mut self) -> Result<i64, Error> {
+        metrics::time_database("get_active_token_count", || self.0.get_active_token_count())
+    }
+
+    fn get_token(&mut self, token: String) -> Result<Option<Token>, Error> {
+        metrics::time_database("get_token", || self.0.get_token(token))
+    }
+
+    fn create_token(&mut self, token: &NewToken) -> Result<String, Error> {
+        metrics::time_database("create_token", || self.0.create_token(token))
+    }
+
+    fn revoke_token_by_id(&mut self, token_id: i32) -> Result<(), Error> {
+        metrics::time_database("revoke_token_by_id", || self.0.revoke_token_by_id(token_id))
+    }
+
+    fn rotate_token(&mut self, token_id: i32) -> Result<String, Error> {
+        metrics::time_database("rotate_token", || self.0.rotate_token(token_id))
+    }
+
+    fn record_token_usage(&mut self, usage: &[TokenUsage]) -> Result<(), Error> {
+        metrics::time_database("record_token_usage", || self.0.record_token_usage(usage))
+    }
+
+    fn get_token_usage(&mut self, token_id: i32) -> Result<Vec<TokenUsage>, Error> {
+        metrics::time_database("get_token_usage", || self.0.get_token_usage(token_id))
+    }
+
+    fn get_inactive_tokens(&mut self, since: NaiveDateTime) -> Result<Vec<Token>, Error> {
+        metrics::time_database("get_inactive_tokens", || self.0.get_inactive_tokens(since))
+    }
+    //endregion
+
+    //region Banlist
+    fn export_bans(&mut self, batch_size: usize, visit: &mut dyn FnMut(Vec<Ban>) -> bool) -> Result<(), Error> {
+        metrics::time_database("export_bans", || self.0.export_bans(batch_size, visit))
+    }
+
+    fn get_bans_page(&mut self, query: &BanQuery) -> Result<Vec<Ban>, Error> {
+        metrics::time_database("get_bans_page", || self.0.get_bans_page(query))
+    }
+
+    fn get_banned_ids(&mut self) -> Result<Vec<i64>, Error> {
+        metrics::time_database("get_banned_ids", || self.0.get_banned_ids())
+    }
+
+    fn get_total_ban_count(&mut self) -> Result<i64, Error> {
+        metrics::time_database("get_total_ban_count", || self.0.get_total_ban_count())
+    }
+
+    fn add_bans(&mut self, bans: &[NewBan], admin_token: i32) -> Result<Vec<BanAction>, Error> {
+        metrics::time_database("add_bans", || self.0.add_bans(bans, admin_token))
+    }
+
+    fn add_bans_partial(&mut self, bans: &[NewBan], admin_token: i32)
+        -> Result<Vec<Result<BanAction, Error>>, Error> {
+        metrics::time_database("add_bans_partial", || self.0.add_bans_partial(bans, admin_token))
+    }
+
+    fn get_ban(&mut self, user_id: i64) -> Result<Option<Ban>, Error> {
+        metrics::time_database("get_ban", || self.0.get_ban(user_id))
+    }
+
+    fn get_bans_by_ids(&mut self, user_ids: &[i64]) -> Result<Vec<Ban>, Error> {
+        metrics::time_database("get_bans_by_ids", || self.0.get_bans_by_ids(user_ids))
+    }
+
+    fn delete_ban(&mut self, user_id: i64, admin_token: i32) -> Result<(), Error> {
+        metrics::time_database("delete_ban", || self.0.delete_ban(user_id, admin_token))
+    }
+
+    fn archive_expired_bans(&mut self) -> Result<u64, Error> {
+        metrics::time_database("archive_expired_bans", || self.0.archive_expired_bans())
+    }
+
+    fn get_ban_history(&mut self, user_id: i64) -> Result<Vec<BanHistory>, Error> {
+        metrics::time_database("get_ban_history", || self.0.get_ban_history(user_id))
+    }
+
+    fn search_bans(&mut self, query: &str, limit: usize) -> Result<Vec<BanMatch>, Error> {
+        metrics::time_database("search_bans", || self.0.search_bans(query, limit))
+    }
+
+    fn get_ban_changes(&mut self, since: ChangesSince) -> Result<BanChanges, Error> {
+        metrics::time_database("get_ban_changes", || self.0.get_ban_changes(since))
+    }
+    //endregion
+
+    //region Ban categories
+    fn get_ban_categories(&mut self) -> Result<Vec<BanCategory>, Error> {
+        metrics::time_database("get_ban_categories", || self.0.get_ban_categories())
+    }
+
+    fn get_ban_category(&mut self, category_id: i32) -> Result<Option<BanCategory>, Error> {
+        metrics::time_database("get_ban_category", || self.0.get_ban_category(category_id))
+    }
+
+    fn create_ban_category(&mut self, name: &str, description: &Option<String>) -> Result<BanCategory, Error> {
+        metrics::time_database("create_ban_category", || self.0.create_ban_category(name, description))
+    }
+
+    fn update_ban_category(&mut self, category: &BanCategory) -> Result<(), Error> {
+        metrics::time_database("update_ban_category", || self.0.update_ban_category(category))
+    }
+
+    fn delete_ban_category(&mut self, category_id: i32) -> Result<bool, Error> {
+        metrics::time_database("delete_ban_category", || self.0.delete_ban_category(category_id))
+    }
+
+    fn get_ban_category_counts(&mut self) -> Result<Vec<(BanCategory, i64)>, Error> {
+        metrics::time_database("get_ban_category_counts", || self.0.get_ban_category_counts())
+    }
+    //endregion
+
+    //region Antiflood
+    fn take_antiflood(&mut self, token_id: i32, endpoint: &str, limit: &Limit) -> Result<RateLimit, Error> {
+        metrics::time_database("take_antiflood", || self.0.take_antiflood(token_id, endpoint, limit))
+    }
+
+    fn get_antiflood_buckets(&mut self, token_id: i32) -> Result<Vec<Antiflood>, Error> {
+        metrics::time_database("get_antiflood_buckets", || self.0.get_antiflood_buckets(token_id))
+    }
+
+    fn reset_antiflood(&mut self, token_id: i32) -> Result<(), Error> {
+        metrics::time_database("reset_antiflood", || self.0.reset_antiflood(token_id))
+    }
+
+    fn get_antiflood_overrides(&mut self, token_id: i32) -> Result<BTreeMap<String, Limit>, Error> {
+        metrics::time_database("get_antiflood_overrides", || self.0.get_antiflood_overrides(token_id))
+    }
+
+    fn set_antiflood_overrides(&mut self, token_id: i32, overrides: &BTreeMap<String, Limit>) -> Result<(), Error> {
+        metrics::time_database("set_antiflood_overrides", || self.0.set_antiflood_overrides(token_id, overrides))
+    }
+    //endregion
+}
@@ -0,0 +1,524 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{NaiveDateTime, Utc};
+
+use crate::database::{Antiflood, Ban, BanAction, BanCategory, BanChanges, BanHistory, BanMatch, BanQuery, BanSort,
+                      ChangesSince, Error, NewBan, NewToken, Storage, Token, TokenUsage};
+use crate::database::migrations::Migration;
+use crate::ratelimit::{self, RateLimit};
+use crate::settings;
+use crate::settings::Limit;
+use crate::utils;
+
+#[derive(Default)]
+struct State {
+    migrations: Vec<String>,
+    tokens: Vec<Token>,
+    token_hashes: HashMap<String, i32>,
+    bans: BTreeMap<i64, Ban>,
+    deletions: BTreeMap<i64, NaiveDateTime>,
+    history: Vec<BanHistory>,
+    categories: BTreeMap<i32, BanCategory>,
+    /// IDs of deleted categories aren't reused, like with a `serial` column
+    last_category_id: i32,
+    antiflood: HashMap<(i32, String), Antiflood>,
+    antiflood_overrides: HashMap<i32, BTreeMap<String, Limit>>,
+    usage: BTreeMap<(i32, String), TokenUsage>,
+}
+
+/// Storage backend that keeps everything in process memory.
+///
+/// Clones share the same state, so a single instance can be handed to every worker.
+#[derive(Clone, Default)]
+pub struct MemoryDatabase {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryDatabase {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn active(ban: &&Ban) -> bool {
+    !matches!(ban.expires, Some(expires) if expires <= now())
+}
+
+impl State {
+    fn record(&mut self, ban_id: i64, action: BanAction, token: Option<i32>,
+              old: Option<&Ban>, new_reason: Option<String>, new_message: Option<String>) {
+        let id = self.history.len() as i32 + 1;
+        self.history.push(BanHistory {
+            id,
+            ban_id,
+            action,
+            token,
+            old_reason: old.map(|b| b.reason.clone()),
+            new_reason,
+            old_message: old.and_then(|b| b.message.clone()),
+            new_message,
+            date: now(),
+        });
+    }
+}
+
+impl Storage for MemoryDatabase {
+    //region Migrations
+    // There is no schema to change, so only the bookkeeping is kept
+    fn applied_migrations(&mut self) -> Result<Vec<String>, Error> {
+        Ok(self.state().migrations.clone())
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), Error> {
+        let mut state = self.state();
+        state.migrations.push(migration.version.to_string());
+        state.migrations.sort();
+        Ok(())
+    }
+
+    fn revert_migration(&mut self, migration: &Migration) -> Result<(), Error> {
+        self.state().migrations.retain(|v| v != migration.version);
+        Ok(())
+    }
+
+    // Only ever used by this process
+    fn lock_migrations(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn unlock_migrations(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+    //endregion
+
+    //region Tokens
+    fn get_tokens(&mut self) -> Result<Vec<Token>, Error> {
+        debug!(utils::LOGGER, "Getting all tokens");
+        Ok(self.state().tokens.clone())
+    }
+
+    fn get_token_by_id(&mut self, token_id: i32) -> Result<Option<Token>, Error> {
+        debug!(utils::LOGGER, "Getting token by id"; "id" => token_id);
+        Ok(self.state().tokens.iter().find(|t| t.id == token_id).cloned())
+    }
+
+    fn get_token_by_userid(&mut self, userid: i64) -> Result<Vec<Token>, Error> {
+        debug!(utils::LOGGER, "Getting token by userid"; "id" => userid);
+        Ok(self.state().tokens.iter().filter(|t| t.userid == userid).cloned().collect())
+    }
+
+    fn get_active_token_count(&mut self) -> Result<i64, Error> {
+        debug!(utils::LOGGER, "Getting active token count");
+        Ok(self.state().tokens.iter().filter(|t| !t.retired && !t.expired()).count() as i64)
+    }
+
+    fn get_token(&mut self, token: String) -> Result<Option<Token>, Error> {
+        debug!(utils::LOGGER, "Getting token");
+        let state = self.state();
+        Ok(state.token_hashes
+            .get(&utils::hash_token(&token))
+            .and_then(|id| state.tokens.iter().find(|t| t.id == *id))
+            .cloned())
+    }
+
+    fn create_token(&mut self, new_token: &NewToken) -> Result<String, Error> {
+        let token = nanoid::generate(settings::ENV.general.token_size as usize);
+        debug!(utils::LOGGER, "Creating Token"; "permission" => format!("{:?}", new_token.permission));
+        let mut state = self.state();
+        let id = state.tokens.len() as i32 + 1;
+        state.token_hashes.insert(utils::hash_token(&token), id);
+        state.tokens.push(Token {
+            id,
+            permission: new_token.permission.clone(),
+            userid: new_token.userid,
+            retired: false,
+            expires_at: new_token.expires_at,
+            name: new_token.name.clone(),
+            description: new_token.description.clone(),
+            created_at: Some(now()),
+            created_by: new_token.created_by,
+            last_used_at: None,
+        });
+        Ok(token)
+    }
+
+    fn revoke_token_by_id(&mut self, token_id: i32) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Revoking token by id"; "id" => token_id);
+        if let Some(token) = self.state().tokens.iter_mut().find(|t| t.id == token_id) {
+            token.retired = true;
+        }
+        Ok(())
+    }
+
+    fn rotate_token(&mut self, token_id: i32) -> Result<String, Error> {
+        let token = nanoid::generate(settings::ENV.general.token_size as usize);
+        debug!(utils::LOGGER, "Rotating token"; "id" => token_id);
+        let mut state = self.state();
+        state.token_hashes.retain(|_, id| *id != token_id);
+        state.token_hashes.insert(utils::hash_token(&token), token_id);
+        Ok(token)
+    }
+
+    fn record_token_usage(&mut self, usage: &[TokenUsage]) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Recording token usage"; "entries" => usage.len());
+        let mut state = self.state();
+        for entry in usage {
+            state.usage
+                .entry((entry.token_id, entry.endpoint.clone()))
+                .and_modify(|recorded| {
+                    recorded.requests += entry.requests;
+                    recorded.last_used_at = recorded.last_used_at.max(entry.last_used_at);
+                })
+                .or_insert_with(|| entry.clone());
+            if let Some(token) = state.tokens.iter_mut().find(|t| t.id == entry.token_id) {
+                token.last_used_at = token.last_used_at.max(Some(entry.last_used_at));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_token_usage(&mut self, token_id: i32) -> Result<Vec<TokenUsage>, Error> {
+        debug!(utils::LOGGER, "Getting token usage"; "id" => token_id);
+        Ok(self.state().usage.values().filter(|u| u.token_id == token_id).cloned().collect())
+    }
+
+    fn get_inactive_tokens(&mut self, since: NaiveDateTime) -> Result<Vec<Token>, Error> {
+        debug!(utils::LOGGER, "Getting inactive tokens");
+        Ok(self.state().tokens
+            .iter()
+            .filter(|t| !t.retired)
+            .filter(|t| !matches!(t.last_used_at, Some(used) if used >= since))
+            .filter(|t| !matches!(t.created_at, Some(created) if created >= since))
+            .cloned()
+            .collect())
+    }
+    //endregion
+
+    //region Banlist
+    fn export_bans(&mut self, batch_size: usize, visit: &mut dyn FnMut(Vec<Ban>) -> bool) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Exporting bans"; "batch_size" => batch_size);
+        let bans: Vec<Ban> = self.state().bans.values().filter(active).cloned().collect();
+        for batch in bans.chunks(batch_size) {
+            if !visit(batch.to_vec()) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_bans_page(&mut self, query: &BanQuery) -> Result<Vec<Ban>, Error> {
+        debug!(utils::LOGGER, "Getting a page of bans"; "limit" => query.limit);
+        let reason = query.reason.as_ref().map(|reason| reason.to_lowercase());
+        let mut bans: Vec<Ban> = self.state()
+            .bans
+            .values()
+            .filter(active)
+            .filter(|ban| query.since.is_none_or(|since| ban.date >= since)
+                && query.until.is_none_or(|until| ban.date < until)
+                && query.admin.is_none_or(|admin| ban.admin == admin)
+                && reason.as_ref().is_none_or(|reason| ban.reason.to_lowercase().contains(reason))
+                && query.has_message.is_none_or(|has_message| ban.message.is_some() == has_message)
+                && query.category.is_none_or(|category| ban.category == Some(category)))
+            .cloned()
+            .collect();
+        // Same order as the `ORDER BY` of the PostgreSQL backend
+        let key = |ban: &Ban| match query.sort {
+            BanSort::Id => (None, ban.id),
+            BanSort::Date => (Some(ban.date), ban.id),
+        };
+        bans.sort_by_key(key);
+        if query.descending {
+            bans.reverse();
+        }
+        if let Some(after_id) = query.after_id {
+            let after = match query.sort {
+                BanSort::Id => (None, after_id),
+                BanSort::Date => (query.after_date, after_id),
+            };
+            bans.retain(|ban| if query.descending { key(ban) < after } else { key(ban) > after });
+        }
+        bans.truncate(query.limit);
+        Ok(bans)
+    }
+
+    fn get_banned_ids(&mut self) -> Result<Vec<i64>, Error> {
+        debug!(utils::LOGGER, "Getting all bans as ids");
+        Ok(self.state().bans.values().filter(active).map(|b| b.id).collect())
+    }
+
+    fn get_total_ban_count(&mut self) -> Result<i64, Error> {
+        debug!(utils::LOGGER, "Getting all bans");
+        Ok(self.state().bans.values().filter(active).count() as i64)
+    }
+
+    fn add_bans(&mut self, bans: &[NewBan], admin_token: i32) -> Result<Vec<BanAction>, Error> {
+        debug!(utils::LOGGER, "Upserting bans"; "count" => bans.len());
+        let mut state = self.state();
+        let mut actions = Vec::with_capacity(bans.len());
+        for ban in bans {
+            // Mirrors the `ON CONFLICT` clause of the PostgreSQL backend, which keeps the original admin
+            let admin = state.bans.get(&ban.id).map_or(admin_token, |old| old.admin);
+            let old_ban = state.bans.insert(ban.id, Ban {
+                id: ban.id,
+                reason: ban.reason.clone(),
+                date: now(),
+                admin,
+                message: ban.message.clone(),
+                expires: ban.expires,
+                category: ban.category,
+            });
+            state.deletions.remove(&ban.id);
+            let action = if old_ban.is_some() { BanAction::Update } else { BanAction::Create };
+            state.record(ban.id, action.clone(), Some(admin_token), old_ban.as_ref(),
+                         Some(ban.reason.clone()), ban.message.clone());
+            actions.push(action);
+        }
+        Ok(actions)
+    }
+
+    // Nothing can fail for a single ban here
+    fn add_bans_partial(&mut self, bans: &[NewBan], admin_token: i32)
+        -> Result<Vec<Result<BanAction, Error>>, Error> {
+        Ok(self.add_bans(bans, admin_token)?.into_iter().map(Ok).collect())
+    }
+
+    fn get_ban(&mut self, user_id: i64) -> Result<Option<Ban>, Error> {
+        debug!(utils::LOGGER, "Getting ban by id"; "id" => user_id);
+        Ok(self.state().bans.get(&user_id).filter(active).cloned())
+    }
+
+    fn get_bans_by_ids(&mut self, user_ids: &[i64]) -> Result<Vec<Ban>, Error> {
+        debug!(utils::LOGGER, "Getting bans by ids"; "count" => user_ids.len());
+        let state = self.state();
+        Ok(user_ids.iter().filter_map(|id| state.bans.get(id)).filter(active).cloned().collect())
+    }
+
+    fn delete_ban(&mut self, user_id: i64, admin_token: i32) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Deleting ban"; "id" => user_id);
+        let mut state = self.state();
+        if let Some(old_ban) = state.bans.remove(&user_id) {
+            state.record(user_id, BanAction::Delete, Some(admin_token), Some(&old_ban), None, None);
+            state.deletions.insert(user_id, now());
+        }
+        Ok(())
+    }
+
+    fn archive_expired_bans(&mut self) -> Result<u64, Error> {
+        debug!(utils::LOGGER, "Archiving expired bans");
+        let mut state = self.state();
+        let expired: Vec<i64> = state.bans.values().filter(|b| !active(b)).map(|b| b.id).collect();
+        for user_id in expired.iter() {
+            if let Some(old_ban) = state.bans.remove(user_id) {
+                state.record(*user_id, BanAction::Expire, None, Some(&old_ban), None, None);
+                state.deletions.insert(*user_id, now());
+            }
+        }
+        Ok(expired.len() as u64)
+    }
+
+    fn get_ban_history(&mut self, user_id: i64) -> Result<Vec<BanHistory>, Error> {
+        debug!(utils::LOGGER, "Getting ban history"; "id" => user_id);
+        Ok(self.state().history.iter().filter(|h| h.ban_id == user_id).cloned().collect())
+    }
+
+    fn search_bans(&mut self, query: &str, limit: usize) -> Result<Vec<BanMatch>, Error> {
+        debug!(utils::LOGGER, "Searching bans"; "search" => query);
+        // Only approximates the full-text search of PostgreSQL: every word of the query has to occur,
+        // words of the reason count more than those of the message, and there are no operators
+        let words: Vec<String> = split_words(query)
+            .into_iter()
+            .filter(|(is_word, _)| *is_word)
+            .map(|(_, word)| word.to_lowercase())
+            .collect();
+        let count = |text: &str, word: &String| split_words(text)
+            .into_iter()
+            .filter(|(is_word, part)| *is_word && part.to_lowercase() == *word)
+            .count() as f32;
+        let mut matches: Vec<BanMatch> = self.state()
+            .bans
+            .values()
+            .filter(active)
+            .filter_map(|ban| {
+                let message = ban.message.as_deref().unwrap_or_default();
+                let ranks: Vec<f32> = words
+                    .iter()
+                    .map(|word| count(&ban.reason, word) + 0.4 * count(message, word))
+                    .collect();
+                if ranks.is_empty() || ranks.contains(&0.0) {
+                    return None;
+                }
+                Some(BanMatch {
+                    ban: ban.clone(),
+                    rank: ranks.iter().sum(),
+                    reason_snippet: highlight(&ban.reason, &words),
+                    message_snippet: ban.message.as_ref().map(|message| highlight(message, &words)),
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| b.rank.partial_cmp(&a.rank).unwrap_or(Ordering::Equal).then(a.ban.id.cmp(&b.ban.id)));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    fn get_ban_changes(&mut self, since: ChangesSince) -> Result<BanChanges, Error> {
+        debug!(utils::LOGGER, "Getting ban changes"; "since" => format!("{:?}", since));
+        let state = self.state();
+        // Every change is recorded in the history, whose IDs are in the order the changes were made
+        let changed_ids: BTreeSet<i64> = match since {
+            ChangesSince::Time(_) => BTreeSet::new(),
+            ChangesSince::Cursor(cursor) => state.history
+                .iter()
+                .filter(|entry| i64::from(entry.id) >= cursor)
+                .map(|entry| entry.ban_id)
+                .collect(),
+        };
+        let changed = |id: &i64, date: &NaiveDateTime| match since {
+            ChangesSince::Time(time) => *date >= time,
+            ChangesSince::Cursor(_) => changed_ids.contains(id),
+        };
+        Ok(BanChanges {
+            added: state.bans.values().filter(active).filter(|b| changed(&b.id, &b.date)).map(|b| b.id).collect(),
+            removed: state.deletions.iter().filter(|(id, date)| changed(id, date)).map(|(id, _)| *id).collect(),
+            cursor: state.history.len() as i64 + 1,
+        })
+    }
+    //endregion
+
+    //region Ban categories
+    fn get_ban_categories(&mut self) -> Result<Vec<BanCategory>, Error> {
+        Ok(self.state().categories.values().cloned().collect())
+    }
+
+    fn get_ban_category(&mut self, category_id: i32) -> Result<Option<BanCategory>, Error> {
+        Ok(self.state().categories.get(&category_id).cloned())
+    }
+
+    fn create_ban_category(&mut self, name: &str, description: &Option<String>) -> Result<BanCategory, Error> {
+        debug!(utils::LOGGER, "Creating ban category"; "name" => name);
+        let mut state = self.state();
+        state.last_category_id += 1;
+        let category = BanCategory {
+            id: state.last_category_id,
+            name: name.to_string(),
+            description: description.clone(),
+        };
+        state.categories.insert(category.id, category.clone());
+        Ok(category)
+    }
+
+    fn update_ban_category(&mut self, category: &BanCategory) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Updating ban category"; "id" => category.id);
+        if let Some(old) = self.state().categories.get_mut(&category.id) {
+            *old = category.clone();
+        }
+        Ok(())
+    }
+
+    fn delete_ban_category(&mut self, category_id: i32) -> Result<bool, Error> {
+        debug!(utils::LOGGER, "Deleting ban category"; "id" => category_id);
+        let mut state = self.state();
+        if state.bans.values().any(|ban| ban.category == Some(category_id)) {
+            return Ok(false);
+        }
+        Ok(state.categories.remove(&category_id).is_some())
+    }
+
+    fn get_ban_category_counts(&mut self) -> Result<Vec<(BanCategory, i64)>, Error> {
+        let state = self.state();
+        Ok(state.categories
+            .values()
+            .map(|category| {
+                let count = state.bans
+                    .values()
+                    .filter(active)
+                    .filter(|ban| ban.category == Some(category.id))
+                    .count();
+                (category.clone(), count as i64)
+            })
+            .collect())
+    }
+    //endregion
+
+    //region Antiflood
+    fn take_antiflood(&mut self, token_id: i32, endpoint: &str, limit: &Limit) -> Result<RateLimit, Error> {
+        debug!(utils::LOGGER, "Taking from antiflood bucket"; "token" => token_id, "endpoint" => endpoint);
+        let now = now();
+        let mut state = self.state();
+        let bucket = state.antiflood
+            .entry((token_id, endpoint.to_string()))
+            .or_insert_with(|| ratelimit::new_bucket(endpoint, limit, now));
+        Ok(ratelimit::take(bucket, limit, now))
+    }
+
+    fn get_antiflood_buckets(&mut self, token_id: i32) -> Result<Vec<Antiflood>, Error> {
+        debug!(utils::LOGGER, "Getting antiflood buckets"; "token" => token_id);
+        let mut buckets: Vec<Antiflood> = self.state().antiflood
+            .iter()
+            .filter(|((token, _), _)| *token == token_id)
+            .map(|(_, bucket)| bucket.clone())
+            .collect();
+        buckets.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        Ok(buckets)
+    }
+
+    fn reset_antiflood(&mut self, token_id: i32) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Resetting antiflood buckets"; "token" => token_id);
+        self.state().antiflood.retain(|(token, _), _| *token != token_id);
+        Ok(())
+    }
+
+    fn get_antiflood_overrides(&mut self, token_id: i32) -> Result<BTreeMap<String, Limit>, Error> {
+        debug!(utils::LOGGER, "Getting antiflood overrides"; "token" => token_id);
+        Ok(self.state().antiflood_overrides.get(&token_id).cloned().unwrap_or_default())
+    }
+
+    fn set_antiflood_overrides(&mut self, token_id: i32, overrides: &BTreeMap<String, Limit>) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Setting antiflood overrides"; "token" => token_id);
+        self.state().antiflood_overrides.insert(token_id, overrides.clone());
+        Ok(())
+    }
+    //endregion
+}
+
+/// Splits the text into runs of alphanumeric and other characters, `true` for the alphanumeric ones.
+fn split_words(text: &str) -> Vec<(bool, &str)> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let is_word = c.is_alphanumeric();
+        match chars.peek() {
+            Some((_, next)) if next.is_alphanumeric() == is_word => {}
+            next => {
+                let end = next.map_or(text.len(), |(i, _)| *i);
+                parts.push((is_word, &text[start..end]));
+                start = end;
+            }
+        }
+    }
+    parts
+}
+
+/// Escapes the text as HTML and wraps the words in `<mark>` tags, like `ts_headline` does.
+fn highlight(text: &str, words: &[String]) -> String {
+    let mut highlighted = String::new();
+    for (is_word, part) in split_words(text) {
+        let escaped = part
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;");
+        if is_word && words.contains(&part.to_lowercase()) {
+            highlighted.push_str(&format!("<mark>{}</mark>", escaped));
+        } else {
+            highlighted.push_str(&escaped);
+        }
+    }
+    highlighted
+}
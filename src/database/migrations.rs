@@ -0,0 +1,106 @@
+use crate::database::{Error, Storage};
+use crate::utils;
+
+pub struct Migration {
+    pub version: &'static str,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:expr) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../migrations/", $version, "_", $name, "/up.sql")),
+            down: include_str!(concat!("../../migrations/", $version, "_", $name, "/down.sql")),
+        }
+    };
+}
+
+/// All migrations shipped with this binary, oldest first.
+pub const MIGRATIONS: &[Migration] = &[
+    migration!("20200612002126", "initial"),
+    migration!("20200612004225", "antiflood"),
+    migration!("20200612020854", "message-field"),
+    migration!("20201018120000", "ban-deletions"),
+    migration!("20201018130000", "ban-history"),
+    migration!("20201018140000", "ban-expiry"),
+    migration!("20201018150000", "token-hashing"),
+    migration!("20201018160000", "token-expiry"),
+    migration!("20201018170000", "token-metadata"),
+    migration!("20201018180000", "token-usage"),
+    migration!("20201018190000", "rate-limits"),
+    migration!("20201018200000", "rate-limit-overrides"),
+    migration!("20201018210000", "ban-search"),
+    migration!("20201018220000", "ban-categories"),
+    migration!("20201018230000", "endpoint-keys"),
+    migration!("20201019000000", "ban-change-txids"),
+];
+
+/// Version of the newest migration this binary knows about.
+pub fn latest_version() -> &'static str {
+    MIGRATIONS.last().map_or("", |m| m.version)
+}
+
+/// Explains how to update the schema when `pending` found migrations to apply.
+pub fn log_out_of_date(pending: usize) {
+    error!(utils::LOGGER, "The database schema is out of date. Run `{} migrate` or enable `migrate` under the `database` section in the config.", env!("CARGO_PKG_NAME");
+        "pending" => pending, "latest" => latest_version());
+}
+
+pub fn pending(db: &mut dyn Storage) -> Result<Vec<&'static Migration>, Error> {
+    let applied = db.applied_migrations()?;
+    if let Some(unknown) = applied.iter().find(|v| !MIGRATIONS.iter().any(|m| &m.version == v)) {
+        return Err(Error::Migration(format!(
+            "database contains migration {} which is unknown to this binary", unknown)));
+    }
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|v| v == m.version))
+        .collect())
+}
+
+/// Runs `f` while holding the migrations lock, so instances starting at the same time
+/// don't apply the same migrations twice.
+fn locked<T>(db: &mut dyn Storage, f: impl FnOnce(&mut dyn Storage) -> Result<T, Error>) -> Result<T, Error> {
+    db.lock_migrations()?;
+    let result = f(db);
+    let unlocked = db.unlock_migrations();
+    let result = result?;
+    unlocked?;
+    Ok(result)
+}
+
+pub fn run_pending(db: &mut dyn Storage) -> Result<usize, Error> {
+    // Another instance may have applied some while this one waited for the lock
+    locked(db, |db| {
+        let pending = pending(db)?;
+        for migration in pending.iter() {
+            info!(utils::LOGGER, "Applying migration";
+                "version" => migration.version, "name" => migration.name);
+            db.apply_migration(migration)?;
+        }
+        Ok(pending.len())
+    })
+}
+
+pub fn rollback(db: &mut dyn Storage, steps: usize) -> Result<usize, Error> {
+    locked(db, |db| {
+        let applied = db.applied_migrations()?;
+        let mut reverted = 0;
+        for version in applied.iter().rev().take(steps) {
+            let migration = MIGRATIONS
+                .iter()
+                .find(|m| m.version == version)
+                .ok_or_else(|| Error::Migration(format!(
+                    "can't roll back migration {} which is unknown to this binary", version)))?;
+            info!(utils::LOGGER, "Reverting migration";
+                "version" => migration.version, "name" => migration.name);
+            db.revert_migration(migration)?;
+            reverted += 1;
+        }
+        Ok(reverted)
+    })
+}
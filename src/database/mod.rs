@@ -0,0 +1,561 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use spamwatch_types as api;
+
+use crate::errors::UserError;
+use crate::guards::Permission;
+use crate::ratelimit::RateLimit;
+use crate::settings;
+use crate::settings::Limit;
+use crate::utils;
+
+pub use spamwatch_types::{BanAction, BanSort};
+
+pub use self::memory::MemoryDatabase;
+pub use self::postgres::Database;
+
+use self::instrumented::Instrumented;
+use self::migrations::Migration;
+
+mod instrumented;
+mod memory;
+pub mod migrations;
+mod postgres;
+
+#[derive(Debug)]
+pub enum Error {
+    Postgres(::postgres::Error),
+    Pool(r2d2::Error),
+    Migration(String),
+}
+
+impl From<::postgres::Error> for Error {
+    fn from(item: ::postgres::Error) -> Self {
+        Error::Postgres(item)
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(item: r2d2::Error) -> Self {
+        Error::Pool(item)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Postgres(e) => write!(f, "{}", e),
+            Error::Pool(e) => write!(f, "{}", e),
+            Error::Migration(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Serialized as `api::Token`.
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub id: i32,
+    pub permission: Permission,
+    pub userid: i64,
+    pub retired: bool,
+    pub expires_at: Option<NaiveDateTime>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Unknown for tokens created before this was recorded
+    pub created_at: Option<NaiveDateTime>,
+    /// ID of the token that created this one, `None` for the genesis token
+    pub created_by: Option<i32>,
+    /// Lags behind by up to `usage_flush_interval` seconds
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// Longest name a token can have.
+pub const MAX_TOKEN_NAME_LENGTH: usize = 64;
+
+lazy_static! {
+    static ref TOKEN_NAME_TOO_LONG: String =
+        format!("token name can't be longer than {} characters", MAX_TOKEN_NAME_LENGTH);
+}
+
+/// Everything needed to create a token.
+#[derive(Debug)]
+pub struct NewToken {
+    pub permission: Permission,
+    pub userid: i64,
+    pub expires_at: Option<NaiveDateTime>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub created_by: Option<i32>,
+}
+
+/// Requests a token made to a single endpoint.
+#[derive(Debug, Clone)]
+pub struct TokenUsage {
+    pub token_id: i32,
+    /// Method and route, e.g. `GET /banlist/{id}`
+    pub endpoint: String,
+    pub requests: i64,
+    pub last_used_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ban {
+    pub id: i64,
+    pub reason: String,
+    pub date: chrono::NaiveDateTime,
+    pub admin: i32,
+    pub message: Option<String>,
+    pub expires: Option<NaiveDateTime>,
+    /// ID of a `BanCategory`, `reason` is free-text detail then
+    pub category: Option<i32>,
+}
+
+/// Everything needed to create or update a ban.
+#[derive(Debug, Clone)]
+pub struct NewBan {
+    pub id: i64,
+    pub reason: String,
+    pub category: Option<i32>,
+    pub message: Option<String>,
+    pub expires: Option<NaiveDateTime>,
+}
+
+/// A managed ban reason. Root tokens maintain the list.
+#[derive(Debug, Clone)]
+pub struct BanCategory {
+    pub id: i32,
+    /// Lowercase slug, unique
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// A single change to a ban, kept in the `ban_history` table.
+#[derive(Debug, Clone)]
+pub struct BanHistory {
+    pub id: i32,
+    pub ban_id: i64,
+    pub action: BanAction,
+    pub token: Option<i32>,
+    pub old_reason: Option<String>,
+    pub new_reason: Option<String>,
+    pub old_message: Option<String>,
+    pub new_message: Option<String>,
+    pub date: NaiveDateTime,
+}
+
+/// Filters, order and position of a page of bans, see `Storage::get_bans_page`.
+#[derive(Debug, Clone)]
+pub struct BanQuery {
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+    pub admin: Option<i32>,
+    /// Substring of the reason, ignoring case
+    pub reason: Option<String>,
+    pub has_message: Option<bool>,
+    pub category: Option<i32>,
+    pub sort: BanSort,
+    pub descending: bool,
+    /// ID of the last ban on the previous page
+    pub after_id: Option<i64>,
+    /// Date of the last ban on the previous page, only needed when sorting by date
+    pub after_date: Option<NaiveDateTime>,
+    pub limit: usize,
+}
+
+/// A ban found by `Storage::search_bans`.
+#[derive(Debug, Clone)]
+pub struct BanMatch {
+    pub ban: Ban,
+    pub rank: f32,
+    /// HTML-escaped, with the matching words in `<mark>` tags
+    pub reason_snippet: String,
+    pub message_snippet: Option<String>,
+}
+
+/// Where a delta sync starts: at a point in time or after a previous one.
+#[derive(Debug, Clone, Copy)]
+pub enum ChangesSince {
+    Time(NaiveDateTime),
+    Cursor(i64),
+}
+
+/// IDs banned and unbanned since a point in time.
+#[derive(Debug)]
+pub struct BanChanges {
+    pub added: Vec<i64>,
+    pub removed: Vec<i64>,
+    /// Where the next sync should start. Changes close to it may be returned again, but none are missed
+    pub cursor: i64,
+}
+
+/// Token bucket of a single token and endpoint, see `ratelimit::take`.
+#[derive(Debug, Clone)]
+pub struct Antiflood {
+    pub endpoint: String,
+    /// Requests left, including fractions of requests refilled since `updated_at`
+    pub available: f64,
+    pub updated_at: NaiveDateTime,
+}
+
+fn timestamp(time: &Option<NaiveDateTime>) -> Option<i64> {
+    time.map(|time| time.timestamp())
+}
+
+impl Token {
+    pub fn to_api(&self) -> api::Token {
+        api::Token {
+            id: self.id,
+            permission: self.permission.clone(),
+            userid: self.userid,
+            retired: self.retired,
+            expires_at: timestamp(&self.expires_at),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            created_at: timestamp(&self.created_at),
+            created_by: self.created_by,
+            last_used_at: timestamp(&self.last_used_at),
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= chrono::Utc::now().naive_utc(),
+            None => false,
+        }
+    }
+}
+
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_api().serialize(serializer)
+    }
+}
+
+impl NewToken {
+    pub fn new(permission: Permission, userid: i64) -> Self {
+        NewToken {
+            permission,
+            userid,
+            expires_at: None,
+            name: None,
+            description: None,
+            created_by: None,
+        }
+    }
+
+    /// Checks a token requested through the API or on the command line. Without `expires_at` it
+    /// expires after the lifetime configured for its permission.
+    pub fn from_api(token: &api::CreateToken, now: i64) -> Result<NewToken, &'static str> {
+        if matches!(&token.name, Some(name) if name.chars().count() > MAX_TOKEN_NAME_LENGTH) {
+            return Err(&TOKEN_NAME_TOO_LONG);
+        }
+        let expires_at = match token.expires_at {
+            Some(expires_at) if expires_at <= now => return Err("token expiry has to be in the future"),
+            Some(expires_at) => Some(NaiveDateTime::from_timestamp_opt(expires_at, 0).ok_or("invalid token expiry")?),
+            None => match *settings::ENV.token_lifetime.get(&token.permission) {
+                0 => None,
+                lifetime => Some(NaiveDateTime::from_timestamp_opt(now + lifetime as i64, 0)
+                    .ok_or("invalid token lifetime")?),
+            },
+        };
+        Ok(NewToken {
+            permission: token.permission.clone(),
+            userid: token.id,
+            expires_at,
+            name: token.name.clone(),
+            description: token.description.clone(),
+            created_by: None,
+        })
+    }
+}
+
+impl NewBan {
+    /// Checks a ban requested through the API or on the command line. `categories` only needs to
+    /// contain the ban's category.
+    pub fn from_api(ban: &api::CreateBan, categories: &[BanCategory], now: i64) -> Result<NewBan, &'static str> {
+        match ban.category {
+            Some(category) if !categories.iter().any(|c| c.id == category) => return Err("ban category doesn't exist"),
+            None if ban.reason.is_empty() => return Err("ban reason can not be empty"),
+            _ => {}
+        }
+        let expires = match ban.expires {
+            Some(expires) if expires <= now => return Err("ban expiry has to be in the future"),
+            Some(expires) => Some(NaiveDateTime::from_timestamp_opt(expires, 0).ok_or("invalid ban expiry")?),
+            None => None,
+        };
+        Ok(NewBan {
+            id: ban.id,
+            reason: ban.reason.clone(),
+            category: ban.category,
+            message: ban.message.clone(),
+            expires,
+        })
+    }
+}
+
+impl TokenUsage {
+    pub fn to_api(&self) -> api::TokenUsage {
+        api::TokenUsage {
+            endpoint: self.endpoint.clone(),
+            requests: self.requests,
+            last_used_at: self.last_used_at.timestamp(),
+        }
+    }
+}
+
+impl Antiflood {
+    pub fn to_api(&self) -> api::Bucket {
+        api::Bucket {
+            endpoint: self.endpoint.clone(),
+            available: self.available,
+            updated_at: self.updated_at.timestamp(),
+        }
+    }
+}
+
+impl Ban {
+    pub fn json(&self) -> Result<Value, UserError> {
+        Ok(serde_json::to_value(self.raw_json())?)
+    }
+
+    pub fn to_api(&self) -> api::Ban {
+        api::Ban {
+            id: self.id,
+            reason: self.reason.clone(),
+            date: self.date.timestamp(),
+            admin: self.admin,
+            message: self.message.clone(),
+            expires: timestamp(&self.expires),
+            category: self.category,
+        }
+    }
+
+    pub fn raw_json(&self) -> Value {
+        json!(self.to_api())
+    }
+}
+
+impl BanCategory {
+    pub fn to_api(&self) -> api::BanCategory {
+        api::BanCategory {
+            id: self.id,
+            name: self.name.clone(),
+            description: self.description.clone(),
+        }
+    }
+}
+
+impl BanHistory {
+    pub fn to_api(&self) -> api::BanHistory {
+        api::BanHistory {
+            id: self.id,
+            ban_id: self.ban_id,
+            action: self.action.clone(),
+            token: self.token,
+            old_reason: self.old_reason.clone(),
+            new_reason: self.new_reason.clone(),
+            old_message: self.old_message.clone(),
+            new_message: self.new_message.clone(),
+            date: self.date.timestamp(),
+        }
+    }
+
+    pub fn raw_json(&self) -> Value {
+        json!(self.to_api())
+    }
+}
+
+impl BanMatch {
+    pub fn to_api(&self) -> api::BanMatch {
+        api::BanMatch {
+            ban: self.ban.to_api(),
+            rank: self.rank,
+            reason_snippet: self.reason_snippet.clone(),
+            message_snippet: self.message_snippet.clone(),
+        }
+    }
+}
+
+/// Everything the routes and guards need from a storage backend.
+pub trait Storage {
+    //region Migrations
+    /// Versions of all applied migrations, oldest first. Only reads, so it works with read-only access.
+    fn applied_migrations(&mut self) -> Result<Vec<String>, Error>;
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), Error>;
+
+    fn revert_migration(&mut self, migration: &Migration) -> Result<(), Error>;
+
+    /// Waits until no other connection is changing the schema and keeps others from doing so
+    /// until `unlock_migrations`.
+    fn lock_migrations(&mut self) -> Result<(), Error>;
+
+    fn unlock_migrations(&mut self) -> Result<(), Error>;
+    //endregion
+
+    //region Tokens
+    fn create_genesis_token(&mut self) -> Result<(), Error> {
+        debug!(utils::LOGGER, "Checking if Genesis Token exists");
+        if self.get_token_by_id(1)?.is_none() {
+            info!(utils::LOGGER, "Genesis Token doesn't exist. Creating one";
+                "size" => settings::ENV.general.token_size);
+            let mut genesis = NewToken::new(Permission::Root, settings::ENV.general.masterid);
+            genesis.name = Some("Genesis Token".to_string());
+            let token = self.create_token(&genesis)?;
+            info!(utils::LOGGER, "Created Genesis Token `{}`. Write this down, this will be the only time you see it.", token)
+        } else {
+            debug!(utils::LOGGER, "Genesis Token exists. Skipping creation.")
+        }
+        Ok(())
+    }
+
+    fn get_tokens(&mut self) -> Result<Vec<Token>, Error>;
+
+    fn get_token_by_id(&mut self, token_id: i32) -> Result<Option<Token>, Error>;
+
+    fn get_token_by_userid(&mut self, userid: i64) -> Result<Vec<Token>, Error>;
+
+    /// Number of tokens that are neither retired nor expired.
+    fn get_active_token_count(&mut self) -> Result<i64, Error>;
+
+    /// Looks a token up by its secret. Only a hash of the secret is stored.
+    fn get_token(&mut self, token: String) -> Result<Option<Token>, Error>;
+
+    /// Creates a token and returns its secret. This is the only time the secret is available.
+    fn create_token(&mut self, token: &NewToken) -> Result<String, Error>;
+
+    fn revoke_token_by_id(&mut self, token_id: i32) -> Result<(), Error>;
+
+    /// Gives the token a new secret and returns it. The old secret stops working right away.
+    fn rotate_token(&mut self, token_id: i32) -> Result<String, Error>;
+
+    /// Adds the requests to the counters and moves `last_used_at` of the tokens forward.
+    fn record_token_usage(&mut self, usage: &[TokenUsage]) -> Result<(), Error>;
+
+    fn get_token_usage(&mut self, token_id: i32) -> Result<Vec<TokenUsage>, Error>;
+
+    /// Tokens that weren't retired, yet neither were used nor created since the given time.
+    fn get_inactive_tokens(&mut self, since: NaiveDateTime) -> Result<Vec<Token>, Error>;
+    //endregion
+
+    //region Banlist
+    // Expired bans are hidden by every getter, even before `archive_expired_bans` removed them
+
+    /// Passes all bans ordered by ID to `visit`, `batch_size` at a time, so they never have to be in
+    /// memory at once. Stops early once `visit` returns `false`.
+    fn export_bans(&mut self, batch_size: usize, visit: &mut dyn FnMut(Vec<Ban>) -> bool) -> Result<(), Error>;
+
+    /// Bans matching the query in its order, starting after the position it gives.
+    fn get_bans_page(&mut self, query: &BanQuery) -> Result<Vec<Ban>, Error>;
+
+    fn get_banned_ids(&mut self) -> Result<Vec<i64>, Error>;
+
+    fn get_total_ban_count(&mut self) -> Result<i64, Error>;
+
+    /// Shorthand for `add_bans` with a single ban.
+    #[cfg(test)]
+    fn add_ban(&mut self, user_id: i64, reason: &str, category: Option<i32>, admin_token: i32,
+               message: &Option<String>, expires: Option<NaiveDateTime>) -> Result<(), Error> {
+        let ban = NewBan {
+            id: user_id,
+            reason: reason.to_string(),
+            category,
+            message: message.clone(),
+            expires,
+        };
+        self.add_bans(&[ban], admin_token).map(|_| ())
+    }
+
+    /// Creates or updates all bans in a single transaction and records each change in the ban
+    /// history. Returns whether each ban was created or updated.
+    fn add_bans(&mut self, bans: &[NewBan], admin_token: i32) -> Result<Vec<BanAction>, Error>;
+
+    /// Like `add_bans`, but a ban that can't be stored is rolled back on its own and its error
+    /// returned in its place, while the others are still stored.
+    fn add_bans_partial(&mut self, bans: &[NewBan], admin_token: i32)
+        -> Result<Vec<Result<BanAction, Error>>, Error>;
+
+    fn get_ban(&mut self, user_id: i64) -> Result<Option<Ban>, Error>;
+
+    fn get_bans_by_ids(&mut self, user_ids: &[i64]) -> Result<Vec<Ban>, Error>;
+
+    /// Removes the ban, records it in the ban history and leaves a tombstone behind for `get_ban_changes`.
+    fn delete_ban(&mut self, user_id: i64, admin_token: i32) -> Result<(), Error>;
+
+    /// Removes all expired bans the same way `delete_ban` does and returns how many there were.
+    fn archive_expired_bans(&mut self) -> Result<u64, Error>;
+
+    fn get_ban_history(&mut self, user_id: i64) -> Result<Vec<BanHistory>, Error>;
+
+    /// Full-text search over reasons and messages, best matches first.
+    fn search_bans(&mut self, query: &str, limit: usize) -> Result<Vec<BanMatch>, Error>;
+
+    fn get_ban_changes(&mut self, since: ChangesSince) -> Result<BanChanges, Error>;
+    //endregion
+
+    //region Ban categories
+    fn get_ban_categories(&mut self) -> Result<Vec<BanCategory>, Error>;
+
+    fn get_ban_category(&mut self, category_id: i32) -> Result<Option<BanCategory>, Error>;
+
+    fn create_ban_category(&mut self, name: &str, description: &Option<String>) -> Result<BanCategory, Error>;
+
+    fn update_ban_category(&mut self, category: &BanCategory) -> Result<(), Error>;
+
+    /// Deletes the category unless a ban still has it, even an expired one. `false` if it's in use.
+    fn delete_ban_category(&mut self, category_id: i32) -> Result<bool, Error>;
+
+    /// Every category with its number of bans, including empty ones.
+    fn get_ban_category_counts(&mut self) -> Result<Vec<(BanCategory, i64)>, Error>;
+    //endregion
+
+    //region Antiflood
+    /// Takes a request out of the token's bucket for the endpoint. The bucket is only read and
+    /// updated by one caller at a time.
+    fn take_antiflood(&mut self, token_id: i32, endpoint: &str, limit: &Limit) -> Result<RateLimit, Error>;
+
+    fn get_antiflood_buckets(&mut self, token_id: i32) -> Result<Vec<Antiflood>, Error>;
+
+    /// Refills all buckets of the token by removing them.
+    fn reset_antiflood(&mut self, token_id: i32) -> Result<(), Error>;
+
+    /// Limits that apply to the token instead of the configured ones, by name of the limit.
+    fn get_antiflood_overrides(&mut self, token_id: i32) -> Result<BTreeMap<String, Limit>, Error>;
+
+    /// Replaces all overrides of the token.
+    fn set_antiflood_overrides(&mut self, token_id: i32, overrides: &BTreeMap<String, Limit>) -> Result<(), Error>;
+    //endregion
+}
+
+/// Hands out connections to the configured storage backend.
+#[derive(Clone)]
+pub enum Pool {
+    Postgres(postgres::PgPool),
+    Memory(MemoryDatabase),
+}
+
+impl Pool {
+    pub fn get(&self) -> Result<Box<dyn Storage>, Error> {
+        match self {
+            Pool::Postgres(pool) => Ok(Box::new(Instrumented(Database::new(pool)?))),
+            Pool::Memory(db) => Ok(Box::new(Instrumented(db.clone()))),
+        }
+    }
+}
+
+pub fn create_pool() -> Result<Pool, Error> {
+    match settings::ENV.database.backend {
+        settings::Backend::Postgres => Ok(Pool::Postgres(postgres::create_pool()?)),
+        settings::Backend::Memory => {
+            warn!(utils::LOGGER, "Using the in-memory storage backend. Nothing will be persisted.");
+            Ok(Pool::Memory(MemoryDatabase::default()))
+        }
+    }
+}
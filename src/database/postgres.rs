@@ -0,0 +1,758 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use postgres::types::ToSql;
+use postgres::{Config, NoTls, Row, Statement, Transaction};
+use r2d2::PooledConnection;
+use r2d2_postgres::PostgresConnectionManager;
+
+use crate::database::{Antiflood, Ban, BanAction, BanCategory, BanChanges, BanHistory, BanMatch, BanQuery, BanSort,
+                      ChangesSince, Error, NewBan, NewToken, Storage, Token, TokenUsage};
+use crate::database::migrations::Migration;
+use crate::ratelimit::{self, RateLimit};
+use crate::settings;
+use crate::settings::Limit;
+use crate::utils;
+
+pub type PgPool = r2d2::Pool<PostgresConnectionManager<NoTls>>;
+
+pub struct Database {
+    conn: PooledConnection<PostgresConnectionManager<NoTls>>,
+}
+
+/// Key of the advisory lock held while migrations are applied or reverted, "SpamWatc" in ASCII
+const MIGRATIONS_LOCK: i64 = 0x5370_616d_5761_7463;
+
+const INSERT_HISTORY: &str = "
+    INSERT INTO ban_history (ban_id, action, token, old_reason, new_reason, old_message, new_message, date)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, now());";
+
+const UPSERT_BAN: &str = "
+    INSERT INTO banlist (id, reason, date, admin_token, message, expires, category)
+    VALUES ($1, $2, now(), $3, $4, $5, $6)
+    ON CONFLICT (id) DO
+    UPDATE SET reason=excluded.reason, date=excluded.date, message=excluded.message, expires=excluded.expires,
+               category=excluded.category, txid=excluded.txid;";
+
+/// Statements run for every ban `add_bans` creates or updates, prepared once per call.
+struct AddBanStatements {
+    get_old_ban: Statement,
+    upsert_ban: Statement,
+    delete_tombstone: Statement,
+    insert_history: Statement,
+}
+
+impl AddBanStatements {
+    fn prepare(transaction: &mut Transaction) -> Result<AddBanStatements, Error> {
+        Ok(AddBanStatements {
+            get_old_ban: transaction.prepare("SELECT reason, message FROM banlist WHERE id = $1 FOR UPDATE;")?,
+            upsert_ban: transaction.prepare(UPSERT_BAN)?,
+            delete_tombstone: transaction.prepare("DELETE FROM banlist_deletions WHERE id = $1;")?,
+            insert_history: transaction.prepare(INSERT_HISTORY)?,
+        })
+    }
+
+    fn add(&self, transaction: &mut Transaction, ban: &NewBan, admin_token: i32) -> Result<BanAction, Error> {
+        let old_ban = transaction.query(&self.get_old_ban, &[&ban.id])?.pop();
+        transaction.execute(&self.upsert_ban, &[&ban.id, &ban.reason, &admin_token, &ban.message, &ban.expires,
+            &ban.category])?;
+        transaction.execute(&self.delete_tombstone, &[&ban.id])?;
+        let (action, old_reason, old_message): (BanAction, Option<String>, Option<String>) = match old_ban {
+            Some(row) => (BanAction::Update, row.get(0), row.get(1)),
+            None => (BanAction::Create, None, None),
+        };
+        transaction.execute(&self.insert_history, &[&ban.id, &action, &admin_token,
+            &old_reason, &ban.reason, &old_message, &ban.message])?;
+        Ok(action)
+    }
+}
+
+fn token_from_row(row: &Row) -> Token {
+    Token {
+        id: row.get(0),
+        permission: row.get(2),
+        userid: row.get(3),
+        retired: row.get(4),
+        expires_at: row.get(5),
+        name: row.get(6),
+        description: row.get(7),
+        created_at: row.get(8),
+        created_by: row.get(9),
+        last_used_at: row.get(10),
+    }
+}
+
+/// Expects the columns `id, reason, date, admin_token, message, expires, category` first.
+fn ban_from_row(row: &Row) -> Ban {
+    Ban {
+        id: row.get(0),
+        reason: row.get(1),
+        date: row.get(2),
+        admin: row.get(3),
+        message: row.get(4),
+        expires: row.get(5),
+        category: row.get(6),
+    }
+}
+
+fn category_from_row(row: &Row) -> BanCategory {
+    BanCategory {
+        id: row.get(0),
+        name: row.get(1),
+        description: row.get(2),
+    }
+}
+
+pub fn create_pool() -> Result<PgPool, r2d2::Error> {
+    debug!(utils::LOGGER, "Creating connection pool";
+     "host" => &settings::ENV.database.host,
+     "port" => settings::ENV.database.port,
+     "name" => &settings::ENV.database.name,
+     "username" => &settings::ENV.database.username,
+     "size" => settings::ENV.database.pool_size);
+    let mut config = Config::new();
+    config
+        .host(&settings::ENV.database.host)
+        .port(settings::ENV.database.port)
+        .dbname(&settings::ENV.database.name)
+        .user(&settings::ENV.database.username)
+        .password(&settings::ENV.database.password)
+        .application_name(&env!("CARGO_PKG_NAME"));
+    let manager = PostgresConnectionManager::new(config, NoTls);
+    let pool = r2d2::Pool::builder()
+        .max_size(settings::ENV.database.pool_size)
+        .min_idle(Some(settings::ENV.database.pool_min_idle))
+        .connection_timeout(Duration::from_secs(settings::ENV.database.pool_timeout))
+        .idle_timeout(Some(Duration::from_secs(settings::ENV.database.pool_idle_timeout)))
+        .build(manager)?;
+    debug!(utils::LOGGER, "Connected to PostgreSQL");
+    Ok(pool)
+}
+
+impl Database {
+    pub fn new(pool: &PgPool) -> Result<Database, r2d2::Error> {
+        Ok(Database { conn: pool.get()? })
+    }
+}
+
+impl Storage for Database {
+    //region Migrations
+    fn applied_migrations(&mut self) -> Result<Vec<String>, Error> {
+        // The table is only created by the first migration, before that nothing is applied
+        let table_exists = "SELECT to_regclass('schema_migrations') IS NOT NULL;";
+        let exists: bool = self.conn.query_one(table_exists, &[])?.get(0);
+        if !exists {
+            return Ok(Vec::new());
+        }
+        let get_migrations = "SELECT version FROM schema_migrations ORDER BY version;";
+        debug!(utils::LOGGER, "Getting applied migrations"; "query" => get_migrations);
+        let result: Vec<Row> = self.conn.query(get_migrations, &[])?;
+        Ok(result
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    fn apply_migration(&mut self, migration: &Migration) -> Result<(), Error> {
+        let create_table = "
+            CREATE TABLE IF NOT EXISTS schema_migrations
+            (
+                version    Text      NOT NULL PRIMARY KEY,
+                name       Text      NOT NULL,
+                applied_at timestamp NOT NULL DEFAULT now()
+            );";
+        let insert_migration = "INSERT INTO schema_migrations (version, name) VALUES ($1, $2);";
+        debug!(utils::LOGGER, "Applying migration";
+            "version" => migration.version, "query" => migration.up);
+        let mut transaction = self.conn.transaction()?;
+        transaction.batch_execute(create_table)?;
+        transaction.batch_execute(migration.up)?;
+        transaction.execute(insert_migration, &[&migration.version, &migration.name])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn revert_migration(&mut self, migration: &Migration) -> Result<(), Error> {
+        let delete_migration = "DELETE FROM schema_migrations WHERE version = $1;";
+        debug!(utils::LOGGER, "Reverting migration";
+            "version" => migration.version, "query" => migration.down);
+        let mut transaction = self.conn.transaction()?;
+        transaction.batch_execute(migration.down)?;
+        transaction.execute(delete_migration, &[&migration.version])?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn lock_migrations(&mut self) -> Result<(), Error> {
+        let lock = "SELECT pg_advisory_lock($1);";
+        debug!(utils::LOGGER, "Locking migrations"; "query" => lock);
+        self.conn.execute(lock, &[&MIGRATIONS_LOCK])?;
+        Ok(())
+    }
+
+    fn unlock_migrations(&mut self) -> Result<(), Error> {
+        let unlock = "SELECT pg_advisory_unlock($1);";
+        debug!(utils::LOGGER, "Unlocking migrations"; "query" => unlock);
+        self.conn.execute(unlock, &[&MIGRATIONS_LOCK])?;
+        Ok(())
+    }
+    //endregion
+
+    //region Tokens
+    fn get_tokens(&mut self) -> Result<Vec<Token>, Error> {
+        let get_all_tokens = "SELECT * FROM tokens;";
+        debug!(utils::LOGGER, "Getting all tokens"; "query" => get_all_tokens);
+        let result: Vec<Row> = self.conn.query(get_all_tokens, &[])?;
+        Ok(result
+            .into_iter()
+            .map(|row| token_from_row(&row))
+            .collect())
+    }
+
+    fn get_token_by_id(&mut self, token_id: i32) -> Result<Option<Token>, Error> {
+        let get_token_by_id = "SELECT * FROM tokens WHERE id = $1;";
+        debug!(utils::LOGGER, "Getting token by id";
+            "id" => token_id, "query" => get_token_by_id);
+        let row: Option<Row> = self.conn.query(get_token_by_id, &[&token_id])?.pop();
+
+        Ok(row.map(|row| token_from_row(&row)))
+    }
+
+    fn get_token_by_userid(&mut self, userid: i64) -> Result<Vec<Token>, Error> {
+        let get_token_by_id = "SELECT * FROM tokens WHERE userid = $1;";
+        debug!(utils::LOGGER, "Getting token by userid";
+            "id" => userid, "query" => get_token_by_id);
+        let result: Vec<Row> = self.conn.query(get_token_by_id, &[&userid])?;
+
+        Ok(result
+            .into_iter()
+            .map(|row| token_from_row(&row))
+            .collect())
+    }
+
+    fn get_active_token_count(&mut self) -> Result<i64, Error> {
+        let get_count = "SELECT COUNT(*) FROM tokens WHERE NOT retired AND (expires_at IS NULL OR expires_at > now());";
+        debug!(utils::LOGGER, "Getting active token count"; "query" => get_count);
+        let row: Row = self.conn.query_one(get_count, &[])?;
+        Ok(row.get(0))
+    }
+
+    fn get_token(&mut self, token: String) -> Result<Option<Token>, Error> {
+        let get_token_by_id = "SELECT * FROM tokens WHERE token_hash = $1;";
+        debug!(utils::LOGGER, "Getting token"; "query" => get_token_by_id);
+        let row: Option<Row> = self.conn.query(get_token_by_id, &[&utils::hash_token(&token)])?.pop();
+
+        Ok(row.map(|row| token_from_row(&row)))
+    }
+
+    fn create_token(&mut self, new_token: &NewToken) -> Result<String, Error> {
+        let token = nanoid::generate(settings::ENV.general.token_size as usize);
+        let insert_token = "
+            INSERT INTO tokens (
+                token_hash,
+                permission,
+                userid,
+                expires_at,
+                name,
+                description,
+                created_at,
+                created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, now(), $7);";
+        debug!(utils::LOGGER, "Creating Token";
+         "query" => insert_token, "permission" => format!("{:?}", new_token.permission));
+        self.conn.execute(insert_token, &[
+            &utils::hash_token(&token),
+            &new_token.permission,
+            &new_token.userid,
+            &new_token.expires_at,
+            &new_token.name,
+            &new_token.description,
+            &new_token.created_by,
+        ])?;
+        Ok(token)
+    }
+
+    fn revoke_token_by_id(&mut self, token_id: i32) -> Result<(), Error> {
+        let revoke_token_by_id = "UPDATE tokens SET retired = true WHERE id = $1;";
+        debug!(utils::LOGGER, "Revoking token by id";
+            "id" => token_id, "query" => revoke_token_by_id);
+        self.conn.query(revoke_token_by_id, &[&token_id])?;
+        Ok(())
+    }
+
+    fn rotate_token(&mut self, token_id: i32) -> Result<String, Error> {
+        let token = nanoid::generate(settings::ENV.general.token_size as usize);
+        let rotate_token = "UPDATE tokens SET token_hash = $1 WHERE id = $2;";
+        debug!(utils::LOGGER, "Rotating token";
+            "id" => token_id, "query" => rotate_token);
+        self.conn.execute(rotate_token, &[&utils::hash_token(&token), &token_id])?;
+        Ok(token)
+    }
+
+    fn record_token_usage(&mut self, usage: &[TokenUsage]) -> Result<(), Error> {
+        let upsert_usage = "
+            INSERT INTO token_usage (token_id, endpoint, requests, last_used_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (token_id, endpoint) DO
+            UPDATE SET requests=token_usage.requests + EXCLUDED.requests,
+                       last_used_at=GREATEST(token_usage.last_used_at, EXCLUDED.last_used_at);";
+        let update_last_used = "UPDATE tokens SET last_used_at = GREATEST(last_used_at, $2) WHERE id = $1;";
+        debug!(utils::LOGGER, "Recording token usage";
+            "entries" => usage.len(), "query" => upsert_usage);
+        let mut transaction = self.conn.transaction()?;
+        for entry in usage {
+            transaction.execute(upsert_usage, &[&entry.token_id, &entry.endpoint, &entry.requests, &entry.last_used_at])?;
+            transaction.execute(update_last_used, &[&entry.token_id, &entry.last_used_at])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_token_usage(&mut self, token_id: i32) -> Result<Vec<TokenUsage>, Error> {
+        let get_usage = "SELECT * FROM token_usage WHERE token_id = $1 ORDER BY endpoint;";
+        debug!(utils::LOGGER, "Getting token usage";
+            "id" => token_id, "query" => get_usage);
+        let result: Vec<Row> = self.conn.query(get_usage, &[&token_id])?;
+        Ok(result
+            .into_iter()
+            .map(|row| TokenUsage {
+                token_id: row.get(0),
+                endpoint: row.get(1),
+                requests: row.get(2),
+                last_used_at: row.get(3),
+            })
+            .collect())
+    }
+
+    fn get_inactive_tokens(&mut self, since: NaiveDateTime) -> Result<Vec<Token>, Error> {
+        let get_inactive_tokens = "
+            SELECT * FROM tokens
+            WHERE NOT retired
+              AND (last_used_at IS NULL OR last_used_at < $1)
+              AND (created_at IS NULL OR created_at < $1)
+            ORDER BY id;";
+        debug!(utils::LOGGER, "Getting inactive tokens"; "query" => get_inactive_tokens);
+        let result: Vec<Row> = self.conn.query(get_inactive_tokens, &[&since])?;
+        Ok(result.iter().map(token_from_row).collect())
+    }
+    //endregion
+
+    //region Banlist
+    fn export_bans(&mut self, batch_size: usize, visit: &mut dyn FnMut(Vec<Ban>) -> bool) -> Result<(), Error> {
+        let export_bans = "
+            SELECT id, reason, date, admin_token, message, expires, category FROM banlist
+            WHERE expires IS NULL OR expires > now()
+            ORDER BY id;";
+        debug!(utils::LOGGER, "Exporting bans"; "batch_size" => batch_size, "query" => export_bans);
+        // Portals only live as long as their transaction
+        let mut transaction = self.conn.transaction()?;
+        let portal = transaction.bind(export_bans, &[])?;
+        loop {
+            let rows: Vec<Row> = transaction.query_portal(&portal, batch_size as i32)?;
+            if rows.is_empty() {
+                break;
+            }
+            let bans = rows.iter().map(ban_from_row).collect();
+            if !visit(bans) {
+                break;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn get_bans_page(&mut self, query: &BanQuery) -> Result<Vec<Ban>, Error> {
+        let reason = query.reason.as_ref().map(|reason| format!("%{}%", escape_like(reason)));
+        let limit = query.limit as i64;
+        let mut conditions = vec!["(expires IS NULL OR expires > now())".to_string()];
+        let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
+        if let Some(since) = &query.since {
+            params.push(since);
+            conditions.push(format!("date >= ${}", params.len()));
+        }
+        if let Some(until) = &query.until {
+            params.push(until);
+            conditions.push(format!("date < ${}", params.len()));
+        }
+        if let Some(admin) = &query.admin {
+            params.push(admin);
+            conditions.push(format!("admin_token = ${}", params.len()));
+        }
+        if let Some(reason) = &reason {
+            params.push(reason);
+            conditions.push(format!("reason ILIKE ${}", params.len()));
+        }
+        match query.has_message {
+            Some(true) => conditions.push("message IS NOT NULL".to_string()),
+            Some(false) => conditions.push("message IS NULL".to_string()),
+            None => {}
+        }
+        if let Some(category) = &query.category {
+            params.push(category);
+            conditions.push(format!("category = ${}", params.len()));
+        }
+        let (direction, comparison) = if query.descending { ("DESC", "<") } else { ("ASC", ">") };
+        match (query.sort, &query.after_id, &query.after_date) {
+            (BanSort::Date, Some(after_id), Some(after_date)) => {
+                params.push(after_date);
+                params.push(after_id);
+                conditions.push(format!("(date, id) {} (${}, ${})", comparison, params.len() - 1, params.len()));
+            }
+            (_, Some(after_id), _) => {
+                params.push(after_id);
+                conditions.push(format!("id {} ${}", comparison, params.len()));
+            }
+            _ => {}
+        }
+        let order = match query.sort {
+            BanSort::Id => format!("id {}", direction),
+            BanSort::Date => format!("date {0}, id {0}", direction),
+        };
+        params.push(&limit);
+        let get_bans = format!("
+            SELECT id, reason, date, admin_token, message, expires, category FROM banlist
+            WHERE {}
+            ORDER BY {}
+            LIMIT ${};", conditions.join(" AND "), order, params.len());
+        debug!(utils::LOGGER, "Getting a page of bans"; "query" => &get_bans);
+        let result: Vec<Row> = self.conn.query(get_bans.as_str(), &params)?;
+        Ok(result.iter().map(ban_from_row).collect())
+    }
+
+    fn get_banned_ids(&mut self) -> Result<Vec<i64>, Error> {
+        let get_all_bans = "SELECT id FROM banlist WHERE expires IS NULL OR expires > now();";
+        debug!(utils::LOGGER, "Getting all bans as ids"; "query" => get_all_bans);
+        let result: Vec<Row> = self.conn.query(get_all_bans, &[])?;
+        Ok(result
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    fn get_total_ban_count(&mut self) -> Result<i64, Error> {
+        let get_all_bans = "SELECT COUNT(*) FROM banlist WHERE expires IS NULL OR expires > now();";
+        debug!(utils::LOGGER, "Getting all bans"; "query" => get_all_bans);
+        let result: Vec<Row> = self.conn.query(get_all_bans, &[])?;
+        let count = match result.get(0) {
+            Some(row) => row.get(0),
+            None => 0
+        };
+        Ok(count)
+    }
+
+    fn add_bans(&mut self, bans: &[NewBan], admin_token: i32) -> Result<Vec<BanAction>, Error> {
+        debug!(utils::LOGGER, "Upserting bans";
+            "count" => bans.len(), "query" => UPSERT_BAN);
+        let mut transaction = self.conn.transaction()?;
+        let statements = AddBanStatements::prepare(&mut transaction)?;
+        let actions = bans
+            .iter()
+            .map(|ban| statements.add(&mut transaction, ban, admin_token))
+            .collect::<Result<Vec<BanAction>, Error>>()?;
+        transaction.commit()?;
+        Ok(actions)
+    }
+
+    fn add_bans_partial(&mut self, bans: &[NewBan], admin_token: i32)
+        -> Result<Vec<Result<BanAction, Error>>, Error> {
+        debug!(utils::LOGGER, "Upserting bans one by one";
+            "count" => bans.len(), "query" => UPSERT_BAN);
+        let mut transaction = self.conn.transaction()?;
+        let statements = AddBanStatements::prepare(&mut transaction)?;
+        let mut actions = Vec::with_capacity(bans.len());
+        for ban in bans {
+            // A savepoint, so a failing ban doesn't abort the whole transaction
+            let mut savepoint = transaction.transaction()?;
+            let action = statements.add(&mut savepoint, ban, admin_token);
+            if action.is_ok() {
+                savepoint.commit()?;
+            } else {
+                savepoint.rollback()?;
+            }
+            actions.push(action);
+        }
+        transaction.commit()?;
+        Ok(actions)
+    }
+
+    fn get_ban(&mut self, user_id: i64) -> Result<Option<Ban>, Error> {
+        let get_ban = "
+            SELECT id, reason, date, admin_token, message, expires, category FROM banlist
+            WHERE id = $1 AND (expires IS NULL OR expires > now());";
+        debug!(utils::LOGGER, "Getting token by id";
+            "id" => user_id, "query" => get_ban);
+        let row: Option<Row> = self.conn.query(get_ban, &[&user_id])?.pop();
+        Ok(row.map(|row| ban_from_row(&row)))
+    }
+
+    fn get_bans_by_ids(&mut self, user_ids: &[i64]) -> Result<Vec<Ban>, Error> {
+        let get_bans = "
+            SELECT id, reason, date, admin_token, message, expires, category FROM banlist
+            WHERE id = ANY($1) AND (expires IS NULL OR expires > now());";
+        debug!(utils::LOGGER, "Getting bans by ids";
+            "count" => user_ids.len(), "query" => get_bans);
+        let result: Vec<Row> = self.conn.query(get_bans, &[&user_ids])?;
+        Ok(result.iter().map(ban_from_row).collect())
+    }
+
+    fn delete_ban(&mut self, user_id: i64, admin_token: i32) -> Result<(), Error> {
+        let delete_ban = "DELETE FROM banlist WHERE id = $1 RETURNING reason, message;";
+        let upsert_tombstone = "
+            INSERT INTO banlist_deletions (id, date)
+            VALUES ($1, now())
+            ON CONFLICT (id) DO
+            UPDATE SET date=excluded.date, txid=excluded.txid;";
+        debug!(utils::LOGGER, "Deleting ban";
+            "id" => user_id, "query" => delete_ban);
+        let mut transaction = self.conn.transaction()?;
+        // IDs that weren't banned don't show up as unbanned in `get_ban_changes`
+        if let Some(old_ban) = transaction.query(delete_ban, &[&user_id])?.pop() {
+            let old_reason: Option<String> = old_ban.get(0);
+            let old_message: Option<String> = old_ban.get(1);
+            transaction.execute(INSERT_HISTORY, &[&user_id, &BanAction::Delete, &admin_token,
+                &old_reason, &None::<String>, &old_message, &None::<String>])?;
+            transaction.execute(upsert_tombstone, &[&user_id])?;
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn archive_expired_bans(&mut self) -> Result<u64, Error> {
+        let archive_expired = "
+            WITH expired AS (
+                DELETE FROM banlist WHERE expires <= now() RETURNING id, reason, message
+            ), history AS (
+                INSERT INTO ban_history (ban_id, action, old_reason, old_message, date)
+                SELECT id, 'Expire', reason, message, now() FROM expired
+            )
+            INSERT INTO banlist_deletions (id, date)
+            SELECT id, now() FROM expired
+            ON CONFLICT (id) DO
+            UPDATE SET date=excluded.date, txid=excluded.txid;";
+        debug!(utils::LOGGER, "Archiving expired bans"; "query" => archive_expired);
+        Ok(self.conn.execute(archive_expired, &[])?)
+    }
+
+    fn get_ban_history(&mut self, user_id: i64) -> Result<Vec<BanHistory>, Error> {
+        let get_history = "
+            SELECT id, ban_id, action, token, old_reason, new_reason, old_message, new_message, date
+            FROM ban_history
+            WHERE ban_id = $1
+            ORDER BY id;";
+        debug!(utils::LOGGER, "Getting ban history";
+            "id" => user_id, "query" => get_history);
+        let result: Vec<Row> = self.conn.query(get_history, &[&user_id])?;
+        Ok(result
+            .into_iter()
+            .map(|row| BanHistory {
+                id: row.get(0),
+                ban_id: row.get(1),
+                action: row.get(2),
+                token: row.get(3),
+                old_reason: row.get(4),
+                new_reason: row.get(5),
+                old_message: row.get(6),
+                new_message: row.get(7),
+                date: row.get(8),
+            })
+            .collect())
+    }
+
+    fn search_bans(&mut self, query: &str, limit: usize) -> Result<Vec<BanMatch>, Error> {
+        let search_bans = "
+            SELECT id, reason, date, admin_token, message, expires, category,
+                ts_rank(search, query) AS rank,
+                ts_headline('simple', escape_html(reason), query, 'HighlightAll=true, StartSel=<mark>, StopSel=</mark>'),
+                ts_headline('simple', escape_html(message), query, 'MaxFragments=2, StartSel=<mark>, StopSel=</mark>')
+            FROM banlist, websearch_to_tsquery('simple', $1) query
+            WHERE search @@ query AND (expires IS NULL OR expires > now())
+            ORDER BY rank DESC, id
+            LIMIT $2;";
+        debug!(utils::LOGGER, "Searching bans"; "search" => query, "query" => search_bans);
+        let result: Vec<Row> = self.conn.query(search_bans, &[&query, &(limit as i64)])?;
+        Ok(result
+            .into_iter()
+            .map(|row| BanMatch {
+                ban: ban_from_row(&row),
+                rank: row.get(7),
+                reason_snippet: row.get(8),
+                message_snippet: row.get(9),
+            })
+            .collect())
+    }
+
+    fn get_ban_changes(&mut self, since: ChangesSince) -> Result<BanChanges, Error> {
+        // Transactions older than the cursor have finished before the changes are read, so all of their
+        // changes are returned now. Those of newer ones may be returned now and again next time.
+        let get_cursor = "SELECT txid_snapshot_xmin(txid_current_snapshot());";
+        let (get_added, get_removed, since): (&str, &str, &(dyn ToSql + Sync)) = match since {
+            ChangesSince::Time(ref time) => (
+                "SELECT id FROM banlist WHERE date >= $1 AND (expires IS NULL OR expires > now());",
+                "SELECT id FROM banlist_deletions WHERE date >= $1;",
+                time,
+            ),
+            ChangesSince::Cursor(ref txid) => (
+                "SELECT id FROM banlist WHERE txid >= $1 AND (expires IS NULL OR expires > now());",
+                "SELECT id FROM banlist_deletions WHERE txid >= $1;",
+                txid,
+            ),
+        };
+        debug!(utils::LOGGER, "Getting ban changes"; "query" => get_added);
+        let cursor: i64 = self.conn.query_one(get_cursor, &[])?.get(0);
+        let added = self.conn.query(get_added, &[since])?;
+        let removed = self.conn.query(get_removed, &[since])?;
+        Ok(BanChanges {
+            added: added.into_iter().map(|row| row.get(0)).collect(),
+            removed: removed.into_iter().map(|row| row.get(0)).collect(),
+            cursor,
+        })
+    }
+    //endregion
+
+    //region Ban categories
+    fn get_ban_categories(&mut self) -> Result<Vec<BanCategory>, Error> {
+        let get_categories = "SELECT id, name, description FROM ban_categories ORDER BY id;";
+        debug!(utils::LOGGER, "Getting ban categories"; "query" => get_categories);
+        let result: Vec<Row> = self.conn.query(get_categories, &[])?;
+        Ok(result.iter().map(category_from_row).collect())
+    }
+
+    fn get_ban_category(&mut self, category_id: i32) -> Result<Option<BanCategory>, Error> {
+        let get_category = "SELECT id, name, description FROM ban_categories WHERE id = $1;";
+        debug!(utils::LOGGER, "Getting ban category";
+            "id" => category_id, "query" => get_category);
+        let row: Option<Row> = self.conn.query(get_category, &[&category_id])?.pop();
+        Ok(row.map(|row| category_from_row(&row)))
+    }
+
+    fn create_ban_category(&mut self, name: &str, description: &Option<String>) -> Result<BanCategory, Error> {
+        let insert_category = "
+            INSERT INTO ban_categories (name, description)
+            VALUES ($1, $2)
+            RETURNING id, name, description;";
+        debug!(utils::LOGGER, "Creating ban category";
+            "name" => name, "query" => insert_category);
+        let row: Row = self.conn.query_one(insert_category, &[&name, &description])?;
+        Ok(category_from_row(&row))
+    }
+
+    fn update_ban_category(&mut self, category: &BanCategory) -> Result<(), Error> {
+        let update_category = "UPDATE ban_categories SET name = $2, description = $3 WHERE id = $1;";
+        debug!(utils::LOGGER, "Updating ban category";
+            "id" => category.id, "query" => update_category);
+        self.conn.execute(update_category, &[&category.id, &category.name, &category.description])?;
+        Ok(())
+    }
+
+    fn delete_ban_category(&mut self, category_id: i32) -> Result<bool, Error> {
+        let delete_category = "
+            DELETE FROM ban_categories
+            WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM banlist WHERE category = $1);";
+        debug!(utils::LOGGER, "Deleting ban category";
+            "id" => category_id, "query" => delete_category);
+        Ok(self.conn.execute(delete_category, &[&category_id])? > 0)
+    }
+
+    fn get_ban_category_counts(&mut self) -> Result<Vec<(BanCategory, i64)>, Error> {
+        let get_counts = "
+            SELECT c.id, c.name, c.description, COUNT(b.id)
+            FROM ban_categories c
+                LEFT JOIN banlist b ON b.category = c.id AND (b.expires IS NULL OR b.expires > now())
+            GROUP BY c.id
+            ORDER BY c.id;";
+        debug!(utils::LOGGER, "Getting ban counts by category"; "query" => get_counts);
+        let result: Vec<Row> = self.conn.query(get_counts, &[])?;
+        Ok(result
+            .iter()
+            .map(|row| (category_from_row(row), row.get(3)))
+            .collect())
+    }
+    //endregion
+
+    //region Antiflood
+    fn take_antiflood(&mut self, token_id: i32, endpoint: &str, limit: &Limit) -> Result<RateLimit, Error> {
+        let get_bucket = "SELECT available, updated_at FROM antiflood WHERE token = $1 AND endpoint = $2 FOR UPDATE;";
+        let upsert_bucket = "
+            INSERT INTO antiflood (token, endpoint, available, updated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (token, endpoint) DO
+            UPDATE SET available=EXCLUDED.available, updated_at=EXCLUDED.updated_at;";
+        debug!(utils::LOGGER, "Taking from antiflood bucket";
+            "token" => token_id, "endpoint" => endpoint, "query" => get_bucket);
+        let now = Utc::now().naive_utc();
+        let mut transaction = self.conn.transaction()?;
+        let mut bucket = match transaction.query(get_bucket, &[&token_id, &endpoint])?.pop() {
+            Some(row) => Antiflood {
+                endpoint: endpoint.to_string(),
+                available: row.get(0),
+                updated_at: row.get(1),
+            },
+            None => ratelimit::new_bucket(endpoint, limit, now),
+        };
+        let rate_limit = ratelimit::take(&mut bucket, limit, now);
+        transaction.execute(upsert_bucket, &[&token_id, &endpoint, &bucket.available, &bucket.updated_at])?;
+        transaction.commit()?;
+        Ok(rate_limit)
+    }
+
+    fn get_antiflood_buckets(&mut self, token_id: i32) -> Result<Vec<Antiflood>, Error> {
+        let get_buckets = "SELECT endpoint, available, updated_at FROM antiflood WHERE token = $1 ORDER BY endpoint;";
+        debug!(utils::LOGGER, "Getting antiflood buckets";
+            "token" => token_id, "query" => get_buckets);
+        let result: Vec<Row> = self.conn.query(get_buckets, &[&token_id])?;
+        Ok(result
+            .into_iter()
+            .map(|row| Antiflood {
+                endpoint: row.get(0),
+                available: row.get(1),
+                updated_at: row.get(2),
+            })
+            .collect())
+    }
+
+    fn reset_antiflood(&mut self, token_id: i32) -> Result<(), Error> {
+        let delete_buckets = "DELETE FROM antiflood WHERE token = $1;";
+        debug!(utils::LOGGER, "Resetting antiflood buckets";
+            "token" => token_id, "query" => delete_buckets);
+        self.conn.execute(delete_buckets, &[&token_id])?;
+        Ok(())
+    }
+
+    fn get_antiflood_overrides(&mut self, token_id: i32) -> Result<BTreeMap<String, Limit>, Error> {
+        let get_overrides = "SELECT name, burst, period FROM antiflood_overrides WHERE token = $1;";
+        debug!(utils::LOGGER, "Getting antiflood overrides";
+            "token" => token_id, "query" => get_overrides);
+        let result: Vec<Row> = self.conn.query(get_overrides, &[&token_id])?;
+        Ok(result
+            .into_iter()
+            .map(|row| (row.get(0), Limit {
+                burst: row.get::<_, i64>(1) as u32,
+                period: row.get::<_, i64>(2) as u64,
+            }))
+            .collect())
+    }
+
+    fn set_antiflood_overrides(&mut self, token_id: i32, overrides: &BTreeMap<String, Limit>) -> Result<(), Error> {
+        let delete_overrides = "DELETE FROM antiflood_overrides WHERE token = $1;";
+        let insert_override = "
+            INSERT INTO antiflood_overrides (token, name, burst, period)
+            VALUES ($1, $2, $3, $4);";
+        debug!(utils::LOGGER, "Setting antiflood overrides";
+            "token" => token_id, "query" => insert_override);
+        let mut transaction = self.conn.transaction()?;
+        transaction.execute(delete_overrides, &[&token_id])?;
+        for (name, limit) in overrides {
+            transaction.execute(insert_override, &[&token_id, name, &i64::from(limit.burst), &(limit.period as i64)])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+    //endregion
+}
+
+/// Escapes the wildcards of `LIKE` patterns, so the text only matches itself.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
@@ -3,10 +3,14 @@ use std::fmt;
 use actix_web::error;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
+use chrono::Utc;
 use failure::Fail;
-use postgres;
-use serde_json::{json, Value};
+use serde_json::json;
+use spamwatch_types as api;
 
+use crate::database;
+use crate::metrics;
+use crate::requestid;
 use crate::utils;
 
 #[derive(Fail, Debug)]
@@ -14,16 +18,19 @@ pub enum UserError {
     Internal,
     NotFound,
     BadRequest(&'static str),
+    /// The request conflicts with the current state, e.g. a name that is taken already
+    Conflict(&'static str),
     MethodNotAllowed,
     Unauthorized,
+    TokenExpired,
     Forbidden,
     TooManyRequests {
         until: i64,
     },
 }
 
-impl From<postgres::Error> for UserError {
-    fn from(item: postgres::Error) -> Self {
+impl From<database::Error> for UserError {
+    fn from(item: database::Error) -> Self {
         error!(utils::LOGGER, "{}", item);
         UserError::Internal
     }
@@ -38,7 +45,7 @@ impl From<serde_json::error::Error> for UserError {
 
 impl fmt::Display for UserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Display: {}", self.to_json())
+        write!(f, "Display: {}", json!(self.to_json()))
     }
 }
 
@@ -53,50 +60,67 @@ impl error::ResponseError for UserError {
 }
 
 impl UserError {
-    fn to_json(&self) -> Value {
+    fn status(&self) -> StatusCode {
         match *self {
-            UserError::Internal => json!({
-                "code": StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                "error": StatusCode::INTERNAL_SERVER_ERROR.canonical_reason()
-            }),
-            UserError::NotFound => json!({
-                "code": StatusCode::NOT_FOUND.as_u16(),
-                "error": StatusCode::NOT_FOUND.canonical_reason()
-            }),
-            UserError::BadRequest(reason) => json!({
-                "code": StatusCode::BAD_REQUEST.as_u16(),
-                "error": StatusCode::BAD_REQUEST.canonical_reason(),
-                "reason": reason
-            }),
-            UserError::MethodNotAllowed => json!({
-                "code": StatusCode::METHOD_NOT_ALLOWED.as_u16(),
-                "error": StatusCode::METHOD_NOT_ALLOWED.canonical_reason()
-            }),
-            UserError::Unauthorized => json!({
-                "code": StatusCode::UNAUTHORIZED.as_u16(),
-                "error": StatusCode::UNAUTHORIZED.canonical_reason()
-            }),
-            UserError::Forbidden => json!({
-                "code": StatusCode::FORBIDDEN.as_u16(),
-                "error": StatusCode::FORBIDDEN.canonical_reason()
-            }),
-            UserError::TooManyRequests { until } => json!({
-                "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
-                "error": StatusCode::TOO_MANY_REQUESTS.canonical_reason(),
-                "until": until
-            }),
+            UserError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            UserError::NotFound => StatusCode::NOT_FOUND,
+            UserError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            UserError::Conflict(_) => StatusCode::CONFLICT,
+            UserError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            UserError::Unauthorized => StatusCode::UNAUTHORIZED,
+            UserError::TokenExpired => StatusCode::UNAUTHORIZED,
+            UserError::Forbidden => StatusCode::FORBIDDEN,
+            UserError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn to_json(&self) -> api::Error {
+        let status = self.status();
+        api::Error {
+            code: status.as_u16(),
+            error: status.canonical_reason().unwrap_or_default().to_string(),
+            reason: match *self {
+                UserError::BadRequest(reason) | UserError::Conflict(reason) => Some(reason.to_string()),
+                UserError::TokenExpired => Some("token expired".to_string()),
+                _ => None,
+            },
+            until: match *self {
+                UserError::TooManyRequests { until } => Some(until),
+                _ => None,
+            },
+            request_id: requestid::current(),
+        }
+    }
+
+    /// Name of the variant, used as metrics label.
+    fn name(&self) -> &'static str {
+        match *self {
+            UserError::Internal => "Internal",
+            UserError::NotFound => "NotFound",
+            UserError::BadRequest(_) => "BadRequest",
+            UserError::Conflict(_) => "Conflict",
+            UserError::MethodNotAllowed => "MethodNotAllowed",
+            UserError::Unauthorized => "Unauthorized",
+            UserError::TokenExpired => "TokenExpired",
+            UserError::Forbidden => "Forbidden",
+            UserError::TooManyRequests { .. } => "TooManyRequests",
         }
     }
 
     pub fn to_response(&self) -> HttpResponse {
+        metrics::ERRORS.with_label_values(&[self.name()]).inc();
         match *self {
             UserError::Internal => HttpResponse::InternalServerError().json(self.to_json()),
             UserError::NotFound => HttpResponse::NotFound().json(self.to_json()),
             UserError::BadRequest(_) => HttpResponse::BadRequest().json(self.to_json()),
+            UserError::Conflict(_) => HttpResponse::Conflict().json(self.to_json()),
             UserError::MethodNotAllowed => HttpResponse::MethodNotAllowed().json(self.to_json()),
             UserError::Unauthorized => HttpResponse::Unauthorized().json(self.to_json()),
+            UserError::TokenExpired => HttpResponse::Unauthorized().json(self.to_json()),
             UserError::Forbidden => HttpResponse::Forbidden().json(self.to_json()),
-            UserError::TooManyRequests { until: _ } => HttpResponse::TooManyRequests().json(self.to_json()),
+            UserError::TooManyRequests { until } => HttpResponse::TooManyRequests()
+                .header("Retry-After", (until - Utc::now().timestamp()).max(0).to_string())
+                .json(self.to_json()),
         }
     }
 }
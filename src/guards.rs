@@ -1,44 +1,66 @@
-use chrono::{Duration, NaiveDateTime, Utc};
-use postgres_types::{FromSql, ToSql};
-use serde::{Deserialize, Serialize};
+use actix_web::HttpRequest;
 
-use crate::database::{Antiflood, Database};
+use crate::database::{Pool, Storage};
 use crate::database::Token;
 use crate::errors::UserError;
+use crate::metrics;
+use crate::ratelimit;
+use crate::ratelimit::OverrideCache;
+use crate::requestid::TokenId;
+use crate::routes;
+use crate::usage::UsageTracker;
+use crate::utils;
 
-#[derive(Debug, ToSql, FromSql, Serialize, Deserialize)]
-#[postgres(name = "permission")]
-pub enum Permission {
-    // Can read from the API
-    User,
-    // Can add IDs to the API
-    Admin,
-    // Can create/revoke tokens
-    Root,
-}
-
+pub use spamwatch_types::Permission;
 
 pub struct TokenGuard {
     pub token: Token,
-    db: Database,
-    antiflood: Antiflood,
+    pub db: Box<dyn Storage>,
 }
 
 impl TokenGuard {
-    pub fn new(token_header: String) -> Result<TokenGuard, UserError> {
-        let mut db = Database::new()?;
+    pub fn new(pool: &Pool, req: &HttpRequest) -> Result<TokenGuard, UserError> {
+        let token_header = utils::get_auth_token(req)?;
+        let mut db = pool.get()?;
         if !token_header.is_empty() {
             let token = match db.get_token(token_header)? {
                 Some(token) => token,
                 None => return Err(UserError::Unauthorized),
             };
-            let antiflood = db.get_antiflood(token.id)?;
 
             if token.retired {
                 return Err(UserError::Unauthorized);
             }
+            if token.expired() {
+                return Err(UserError::TokenExpired);
+            }
+            req.extensions_mut().insert(TokenId(token.id));
+            let endpoint = utils::endpoint(req);
+            if let Some(usage) = req.app_data::<UsageTracker>() {
+                usage.record(token.id, endpoint.clone());
+            }
+            let name = routes::limit_name(req);
+            let mut overrides = match req.app_data::<OverrideCache>() {
+                Some(cache) => cache.get(db.as_mut(), token.id)?,
+                None => db.get_antiflood_overrides(token.id)?,
+            };
+            let limit = match overrides.remove(name) {
+                Some(limit) => limit,
+                None => ratelimit::configured_limit(name, &token.permission).clone(),
+            };
+            // Taking from the bucket is a transaction that locks and updates its row, timed as
+            // `take_antiflood` in `spamwatch_database_duration_seconds`. Unlimited endpoints skip it.
+            if !limit.unlimited() {
+                let rate_limit = db.take_antiflood(token.id, &endpoint, &limit)?;
+                let retry_at = rate_limit.retry_at;
+                req.extensions_mut().insert(rate_limit);
+                if let Some(until) = retry_at {
+                    metrics::RATE_LIMITED.with_label_values(&[name]).inc();
+                    return Err(UserError::TooManyRequests { until });
+                }
+            }
 
-            Ok(TokenGuard { token, db, antiflood })
+            Ok(TokenGuard { token, db })
         } else {
             return Err(UserError::Unauthorized);
         }
@@ -58,18 +80,4 @@ impl TokenGuard {
             _ => false,
         }
     }
-
-    pub fn banlist_all(&mut self) -> Result<(), UserError> {
-        if self.admin() {
-            return Ok(());
-        }
-        let current_time = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
-        if self.antiflood.banlist_all < current_time {
-            self.db.set_antiflood_banlist_all(self.token.id,
-                                              current_time + Duration::minutes(5))?;
-            Ok(())
-        } else {
-            return Err(UserError::TooManyRequests { until: self.antiflood.banlist_all.timestamp() });
-        }
-    }
 }
@@ -0,0 +1,41 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::database::Pool;
+use crate::settings;
+use crate::usage::UsageTracker;
+use crate::utils;
+
+/// Periodically moves expired bans out of the banlist.
+pub fn spawn_expiry_sweeper(pool: Pool) {
+    let interval = settings::ENV.general.expiry_interval;
+    if interval == 0 {
+        info!(utils::LOGGER, "Expired ban sweeper is disabled");
+        return;
+    }
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(interval));
+        match pool.get().and_then(|mut db| db.archive_expired_bans()) {
+            Ok(0) => {}
+            Ok(count) => info!(utils::LOGGER, "Archived expired bans"; "count" => count),
+            Err(e) => error!(utils::LOGGER, "Could not archive expired bans"; "error" => e.to_string()),
+        }
+    });
+}
+
+/// Periodically writes the token usage collected by the `UsageTracker` to the database.
+pub fn spawn_usage_flusher(pool: Pool, usage: UsageTracker) {
+    let interval = settings::ENV.general.usage_flush_interval.max(1);
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(interval));
+        flush_usage(&pool, &usage);
+    });
+}
+
+pub fn flush_usage(pool: &Pool, usage: &UsageTracker) {
+    match pool.get().and_then(|mut db| usage.flush(&mut *db)) {
+        Ok(0) => {}
+        Ok(count) => debug!(utils::LOGGER, "Flushed token usage"; "entries" => count),
+        Err(e) => error!(utils::LOGGER, "Could not flush token usage"; "error" => e.to_string()),
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use slog::{BorrowedKV, Drain, Level, Logger, Never, OwnedKVList, Record, RecordStatic, SingleKV};
+
+use crate::requestid;
+use crate::settings::{LogFormat, LogOutput, LoggingCfg};
+
+type BoxedDrain = Box<dyn Drain<Ok=(), Err=Never> + Send>;
+
+/// Logger for the time before the settings are loaded, which `utils::LOGGER` depends on.
+pub fn bootstrap() -> Logger {
+    let decorator = slog_term::TermDecorator::new().stderr().build();
+    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
+    let drain = slog_async::Async::new(drain).build().fuse();
+
+    Logger::root(drain, o!())
+}
+
+pub fn logger(cfg: &LoggingCfg) -> Logger {
+    let drain: BoxedDrain = match cfg.output {
+        LogOutput::Stderr => stderr_drain(&cfg.format),
+        LogOutput::File | LogOutput::Both => match RotatingFile::open(cfg) {
+            Ok(file) if cfg.output == LogOutput::Both => Box::new(
+                slog::Duplicate::new(stderr_drain(&cfg.format), file_drain(&cfg.format, file)).fuse()
+            ),
+            Ok(file) => file_drain(&cfg.format, file),
+            Err(e) => {
+                let logger = Logger::root(Mutex::new(stderr_drain(&cfg.format)).fuse(), o!());
+                error!(logger, "Can't open the log file. Logging to stderr instead";
+                    "file" => &cfg.file, "error" => e.to_string());
+                stderr_drain(&cfg.format)
+            }
+        },
+    };
+    let drain = drain.filter_level(Level::from(&cfg.level)).fuse();
+    let drain = slog_async::Async::new(drain).build().fuse();
+
+    Logger::root(RequestIdDrain(drain), o!())
+}
+
+fn stderr_drain(format: &LogFormat) -> BoxedDrain {
+    match format {
+        LogFormat::Terminal => {
+            let decorator = slog_term::TermDecorator::new().stderr().build();
+            Box::new(slog_term::CompactFormat::new(decorator).build().fuse())
+        }
+        LogFormat::Json => Box::new(slog_json::Json::new(io::stderr()).add_default_keys().build().fuse()),
+    }
+}
+
+/// Adds the ID of the request that is being handled to records.
+/// It's read from a thread local, so this has to wrap the async drain instead of running on its thread.
+pub struct RequestIdDrain<D>(pub D);
+
+impl<D: Drain> Drain for RequestIdDrain<D> {
+    type Ok = D::Ok;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        match requestid::current() {
+            Some(id) => {
+                let rstatic = RecordStatic {
+                    location: record.location(),
+                    tag: record.tag(),
+                    level: record.level(),
+                };
+                let kv = (SingleKV("request_id", id), record.kv());
+                self.0.log(&Record::new(&rstatic, record.msg(), BorrowedKV(&kv)), values)
+            }
+            None => self.0.log(record, values),
+        }
+    }
+}
+
+// A full disk shouldn't take the server down, so errors writing the file are ignored
+fn file_drain(format: &LogFormat, file: RotatingFile) -> BoxedDrain {
+    match format {
+        LogFormat::Terminal => {
+            let decorator = slog_term::PlainDecorator::new(file);
+            Box::new(slog_term::CompactFormat::new(decorator).build().ignore_res())
+        }
+        LogFormat::Json => Box::new(slog_json::Json::new(file).add_default_keys().build().ignore_res()),
+    }
+}
+
+/// Log file that is moved to `<file>.1` once it grew beyond `max_size` bytes.
+/// Older files are moved up by one, and the ones beyond `keep` are deleted.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: u32,
+    file: File,
+    size: u64,
+    /// Records are written in pieces, rotating is only done between lines
+    line_start: bool,
+}
+
+impl RotatingFile {
+    pub fn open(cfg: &LoggingCfg) -> io::Result<RotatingFile> {
+        let path = PathBuf::from(&cfg.file);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_size: cfg.max_size,
+            keep: cfg.keep,
+            file,
+            size,
+            line_start: true,
+        })
+    }
+
+    fn rotated(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_size > 0 && self.size >= self.max_size && self.line_start {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        if written > 0 {
+            self.line_start = buf[written - 1] == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -2,36 +2,64 @@ extern crate postgres;
 #[macro_use]
 extern crate slog;
 
+use std::env;
 use std::process::exit;
 
+use actix_rt::System;
 use actix_web::{App, HttpServer, web};
 
-use crate::database::Database;
+use crate::database::{migrations, Pool};
 use crate::errors::UserError;
+use crate::metrics::Metrics;
+use crate::ratelimit::{OverrideCache, RateLimitHeaders};
+use crate::requestid::RequestLog;
+use crate::usage::UsageTracker;
 
 #[macro_use]
 mod utils;
+mod cli;
 mod database;
 mod errors;
 mod guards;
+mod jobs;
+mod logging;
+mod metrics;
+mod ratelimit;
+mod requestid;
 mod routes;
 mod settings;
+mod usage;
 #[cfg(test)]
 mod tests;
 
-fn setup_database() -> Result<i32, postgres::Error> {
-    let mut db = match Database::new() {
+fn setup_database(pool: &Pool) -> Result<i32, database::Error> {
+    let mut db = match pool.get() {
         Ok(d) => d,
         Err(e) => {
             error!(utils::LOGGER, "A Error occured while connecting to PostgreSQL"; "error" => e.to_string());
             return Ok(1);
         }
     };
+    let pending = match migrations::pending(&mut *db) {
+        Ok(p) => p.len(),
+        Err(e) => {
+            error!(utils::LOGGER, "Could not check the database schema"; "error" => e.to_string());
+            return Ok(1);
+        }
+    };
+    if pending > 0 {
+        if settings::ENV.database.migrate {
+            migrations::run_pending(&mut *db)?;
+        } else {
+            migrations::log_out_of_date(pending);
+            return Ok(1);
+        }
+    }
     db.create_genesis_token()?;
     Ok(0)
 }
 
-fn run() -> Result<i32, postgres::Error> {
+fn run() -> Result<i32, database::Error> {
     info!(utils::LOGGER, "Starting {}", env!("CARGO_PKG_NAME"); "version" => &env!("CARGO_PKG_VERSION"));
     if settings::ENV.general.masterid == 777000 {
         warn!(utils::LOGGER, "MasterID not set. Defaulting to Telegrams id (777000). To avoid this set `masterid` under the `general` section in the config.")
@@ -41,63 +69,74 @@ fn run() -> Result<i32, postgres::Error> {
         "Master ID is {}",
         settings::ENV.general.masterid
     );
-    let db_code = setup_database()?;
+    let pool = match database::create_pool() {
+        Ok(p) => p,
+        Err(e) => {
+            error!(utils::LOGGER, "A Error occured while connecting to PostgreSQL"; "error" => e.to_string());
+            return Ok(1);
+        }
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&pool, &args);
+    }
+    let db_code = setup_database(&pool)?;
     if db_code > 0 {
         return Ok(db_code);
     }
+    jobs::spawn_expiry_sweeper(pool.clone());
+    let usage = UsageTracker::default();
+    jobs::spawn_usage_flusher(pool.clone(), usage.clone());
     let location = format!(
         "{}:{}",
         settings::ENV.server.host,
         settings::ENV.server.port
     );
     info!(utils::LOGGER, "Starting Server on {}", location);
-    HttpServer::new(|| {
+    let metrics = &settings::ENV.metrics;
+    let metrics_on_api = metrics.enabled && metrics.bind.is_empty();
+    let system = System::new(env!("CARGO_PKG_NAME"));
+    let server_pool = pool.clone();
+    let server_usage = usage.clone();
+    let overrides = OverrideCache::default();
+    HttpServer::new(move || {
         App::new()
+            .data(server_pool.clone())
+            .data(server_usage.clone())
+            .data(overrides.clone())
+            .wrap(RateLimitHeaders)
+            .wrap(Metrics)
+            .wrap(RequestLog)
+            .configure(|cfg| {
+                if metrics_on_api {
+                    cfg.route(routes::METRICS_PATH, web::get().to(routes::root::metrics));
+                }
+            })
             .default_service(web::route().to(|| UserError::NotFound.to_response()))
-            .service(
-                web::resource("/")
-                    .route(web::get().to(routes::root::info))
-                    .route(web::head().to(routes::root::info)),
-            )
-            .service(web::resource("/version").route(web::get().to(routes::root::version)))
-            .service(web::resource("/stats").route(web::get().to(routes::root::stats)))
-            .service(
-                web::resource("/tokens")
-                    .route(web::get().to(routes::tokens::get_tokens))
-                    .route(web::post().to(routes::tokens::post_tokens)),
-            )
-            .service(
-                web::resource("/tokens/{id}")
-                    .route(web::get().to(routes::tokens::get_token))
-                    .route(web::delete().to(routes::tokens::delete_token)),
-            )
-            .service(
-                web::resource("/tokens/userid/{uid}")
-                    .route(web::get().to(routes::tokens::get_token_by_userid))
-            )
-            .service(
-                web::resource("/banlist")
-                    .route(web::get().to(routes::banlist::get_bans))
-                    .route(web::post().to(routes::banlist::post_bans)),
-            )
-            .service(
-                web::resource("/banlist/all")
-                    .route(web::get().to(routes::banlist::get_bans_id_list))
-            )
-            .service(
-                web::resource("/banlist/{id}")
-                    .route(web::get().to(routes::banlist::get_ban))
-                    .route(web::delete().to(routes::banlist::delete_ban)),
-            )
+            .configure(routes::configure)
     })
         .bind(location)
         .unwrap()
-        .run()
-        .unwrap();
+        .start();
+    if metrics.enabled && !metrics_on_api {
+        info!(utils::LOGGER, "Serving metrics on {}", metrics.bind);
+        let metrics_pool = pool.clone();
+        HttpServer::new(move || {
+            App::new()
+                .data(metrics_pool.clone())
+                .route(routes::METRICS_PATH, web::get().to(routes::root::metrics))
+        })
+            .bind(&metrics.bind)
+            .unwrap()
+            .start();
+    }
+    system.run().unwrap();
+    // Don't lose the usage collected since the last flush
+    jobs::flush_usage(&pool, &usage);
     Ok(0)
 }
 
-fn main() -> Result<(), postgres::Error> {
+fn main() -> Result<(), database::Error> {
     let exit_code = run()?;
     exit(exit_code);
 }
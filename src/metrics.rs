@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use futures::future::{ok, FutureResult};
+use futures::{Future, Poll};
+use lazy_static::lazy_static;
+use prometheus::{
+    HistogramVec, IntCounterVec, IntGauge, register_histogram_vec, register_int_counter_vec,
+    register_int_gauge,
+};
+
+use crate::utils;
+
+lazy_static! {
+    pub static ref HTTP_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "spamwatch_http_requests_total",
+        "Requests by method, route and status code",
+        &["method", "route", "status"]
+    ).unwrap();
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "spamwatch_http_request_duration_seconds",
+        "Time it took to answer requests, by method and route",
+        &["method", "route"]
+    ).unwrap();
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "spamwatch_errors_total",
+        "Error responses by UserError variant",
+        &["error"]
+    ).unwrap();
+    pub static ref DATABASE_DURATION: HistogramVec = register_histogram_vec!(
+        "spamwatch_database_duration_seconds",
+        "Time spent in the storage backend, by method",
+        &["method"]
+    ).unwrap();
+    pub static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec!(
+        "spamwatch_rate_limited_total",
+        "Requests rejected by the rate limiter, by limit",
+        &["limit"]
+    ).unwrap();
+    pub static ref ACTIVE_TOKENS: IntGauge = register_int_gauge!(
+        "spamwatch_active_tokens",
+        "Tokens that are neither retired nor expired"
+    ).unwrap();
+    pub static ref BANS: IntGauge = register_int_gauge!(
+        "spamwatch_bans",
+        "Active bans"
+    ).unwrap();
+}
+
+/// Runs `f` and records how long it took under the given storage method.
+pub fn time_database<T>(method: &str, f: impl FnOnce() -> T) -> T {
+    let _timer = DATABASE_DURATION.with_label_values(&[method]).start_timer();
+    f()
+}
+
+/// Counts requests and records their latency per route.
+pub struct Metrics;
+
+impl<S, B> Transform<S> for Metrics
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>,
+        S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = MetricsMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(MetricsMiddleware { service })
+    }
+}
+
+pub struct MetricsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for MetricsMiddleware<S>
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>,
+        S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Box<dyn Future<Item=Self::Response, Error=Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        Box::new(self.service.call(req).map(move |res| {
+            let req = res.request();
+            // Only registered patterns, raw paths would give every scanner its own time series
+            let route = utils::route(req);
+            let method = req.method().to_string();
+            HTTP_REQUESTS
+                .with_label_values(&[&method, route, res.status().as_str()])
+                .inc();
+            HTTP_REQUEST_DURATION
+                .with_label_values(&[&method, route])
+                .observe(start.elapsed().as_secs_f64());
+            res
+        }))
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use chrono::NaiveDateTime;
+use futures::future::{ok, FutureResult};
+use futures::{Future, Poll};
+
+use crate::database::{Antiflood, Error, Storage};
+use crate::guards::Permission;
+use crate::settings;
+use crate::settings::Limit;
+
+/// State of a token bucket after a request was taken out of it.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Unix timestamp at which the bucket is full again
+    pub reset: i64,
+    /// Unix timestamp at which the next request is allowed, if this one was rejected
+    pub retry_at: Option<i64>,
+}
+
+impl RateLimit {
+    fn add_headers(&self, headers: &mut HeaderMap) {
+        let values = [
+            ("x-ratelimit-limit", i64::from(self.limit)),
+            ("x-ratelimit-remaining", i64::from(self.remaining)),
+            ("x-ratelimit-reset", self.reset),
+        ];
+        for (name, value) in values.iter() {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(*value));
+        }
+    }
+}
+
+/// Names of all limits in `settings::RateLimitCfg`
+pub const LIMITS: &[&str] = &["default", "banlist_all"];
+
+/// Limit from the config, which applies unless it was overridden for the token.
+pub fn configured_limit(name: &str, permission: &Permission) -> &'static Limit {
+    let limits = &settings::ENV.rate_limit;
+    match name {
+        "banlist_all" => limits.banlist_all.get(permission),
+        _ => limits.default.get(permission),
+    }
+}
+
+/// How long overrides are cached. Other instances see changes only after this.
+const OVERRIDES_TTL: Duration = Duration::from_secs(60);
+
+/// Overrides of a token and when they were loaded
+type CachedOverrides = (Instant, BTreeMap<String, Limit>);
+
+/// Caches the limit overrides of tokens, so authenticating a request doesn't cost a query for them.
+/// Shared between all workers, `invalidate` makes changes visible immediately on this instance.
+#[derive(Clone, Default)]
+pub struct OverrideCache {
+    entries: Arc<Mutex<HashMap<i32, CachedOverrides>>>,
+}
+
+impl OverrideCache {
+    pub fn get(&self, db: &mut dyn Storage, token_id: i32) -> Result<BTreeMap<String, Limit>, Error> {
+        {
+            let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((loaded_at, overrides)) = entries.get(&token_id) {
+                if loaded_at.elapsed() < OVERRIDES_TTL {
+                    return Ok(overrides.clone());
+                }
+            }
+        }
+        let overrides = db.get_antiflood_overrides(token_id)?;
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (loaded_at, _)| loaded_at.elapsed() < OVERRIDES_TTL);
+        entries.insert(token_id, (Instant::now(), overrides.clone()));
+        Ok(overrides)
+    }
+
+    pub fn invalidate(&self, token_id: i32) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).remove(&token_id);
+    }
+}
+
+pub fn new_bucket(endpoint: &str, limit: &Limit, now: NaiveDateTime) -> Antiflood {
+    Antiflood {
+        endpoint: endpoint.to_string(),
+        available: f64::from(limit.burst),
+        updated_at: now,
+    }
+}
+
+/// Refills the bucket for the time passed since it was last updated and takes one request out of it.
+/// Rejected requests don't take anything.
+pub fn take(bucket: &mut Antiflood, limit: &Limit, now: NaiveDateTime) -> RateLimit {
+    let burst = f64::from(limit.burst);
+    let per_second = burst / limit.period as f64;
+    let elapsed = (now - bucket.updated_at).num_milliseconds().max(0) as f64 / 1000.0;
+    bucket.available = (bucket.available + elapsed * per_second).min(burst);
+    bucket.updated_at = now;
+
+    let retry_at = if bucket.available >= 1.0 {
+        bucket.available -= 1.0;
+        None
+    } else {
+        Some(now.timestamp() + ((1.0 - bucket.available) / per_second).ceil() as i64)
+    };
+    RateLimit {
+        limit: limit.burst,
+        remaining: bucket.available.floor() as u32,
+        reset: now.timestamp() + ((burst - bucket.available) / per_second).ceil() as i64,
+        retry_at,
+    }
+}
+
+/// Adds `X-RateLimit-*` headers to responses of requests that were counted against a bucket.
+pub struct RateLimitHeaders;
+
+impl<S, B> Transform<S> for RateLimitHeaders
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>,
+        S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RateLimitHeadersMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitHeadersMiddleware { service })
+    }
+}
+
+pub struct RateLimitHeadersMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RateLimitHeadersMiddleware<S>
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>,
+        S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Box<dyn Future<Item=Self::Response, Error=Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        Box::new(self.service.call(req).map(|mut res| {
+            let rate_limit = res.request().extensions().get::<RateLimit>().cloned();
+            if let Some(rate_limit) = rate_limit {
+                rate_limit.add_headers(res.headers_mut());
+            }
+            res
+        }))
+    }
+}
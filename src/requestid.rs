@@ -0,0 +1,121 @@
+use std::cell::RefCell;
+use std::time::Instant;
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use futures::future::{ok, FutureResult};
+use futures::{Future, Poll};
+
+use crate::utils;
+
+pub const HEADER: &str = "x-request-id";
+/// Longer incoming IDs are replaced, so clients can't flood the logs through them
+const MAX_LENGTH: usize = 128;
+
+thread_local! {
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// ID of the request that is handled on this thread right now.
+pub fn current() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+fn with_id<T>(id: &str, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|current| current.replace(Some(id.to_string())));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// ID of the token that authenticated the request, set by `TokenGuard`.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenId(pub i32);
+
+fn incoming_id(req: &ServiceRequest) -> Option<String> {
+    let id = req.headers().get(HEADER)?.to_str().ok()?;
+    if id.is_empty() || id.len() > MAX_LENGTH || !id.chars().all(|c| c.is_ascii_graphic()) {
+        return None;
+    }
+    Some(id.to_string())
+}
+
+/// Future that makes the request ID current whenever it is polled.
+struct Scoped<F> {
+    id: String,
+    inner: F,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = &mut self.inner;
+        with_id(&self.id, || inner.poll())
+    }
+}
+
+/// Assigns every request an ID, unless the client sent one in `X-Request-Id`, and logs it once answered.
+/// Records logged while handling the request get the ID through `logging::RequestIdDrain`.
+pub struct RequestLog;
+
+impl<S, B> Transform<S> for RequestLog
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>,
+        S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RequestLogMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestLogMiddleware { service })
+    }
+}
+
+pub struct RequestLogMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for RequestLogMiddleware<S>
+    where
+        S: Service<Request=ServiceRequest, Response=ServiceResponse<B>, Error=actix_web::Error>,
+        S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Box<dyn Future<Item=Self::Response, Error=Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let start = Instant::now();
+        let id = incoming_id(&req).unwrap_or_else(nanoid::simple);
+        let header = HeaderValue::from_str(&id).unwrap();
+        let service = &mut self.service;
+        // Sync handlers already run in here
+        let fut = with_id(&id, || service.call(req));
+        Box::new(Scoped {
+            id,
+            inner: fut.map(move |mut res| {
+                let token = res.request().extensions().get::<TokenId>().map(|token| token.0);
+                info!(utils::LOGGER, "Request";
+                    "method" => res.request().method().as_str(),
+                    "path" => res.request().path(),
+                    "status" => res.status().as_u16(),
+                    "latency_ms" => start.elapsed().as_secs_f64() * 1000.0,
+                    "token" => token);
+                res.headers_mut().insert(HeaderName::from_static(HEADER), header);
+                res
+            }),
+        })
+    }
+}
@@ -1,86 +1,410 @@
+use std::thread;
+
+use actix_web::http::header;
+use actix_web::web::Bytes;
 use actix_web::{HttpRequest, HttpResponse, Result, web};
+use chrono::{NaiveDateTime, Utc};
+use futures::sync::mpsc;
+use futures::{Sink, Stream};
 use serde::Deserialize;
 use serde_json::Value;
+use spamwatch_types as api;
 
-use crate::database::Database;
+use crate::database::{Ban, BanAction, BanCategory, BanMatch, BanQuery, BanSort, ChangesSince, NewBan, Pool};
 use crate::errors::UserError;
 use crate::guards::TokenGuard;
+use crate::settings;
 use crate::utils;
 
 #[derive(Debug, Deserialize)]
-pub struct CreateBan {
-    id: i64,
-    reason: String,
-    message: Option<String>,
+pub struct ChangesQuery {
+    since: Option<i64>,
+    cursor: Option<String>,
 }
 
-pub fn get_bans(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
-    if guard.root() {
-        let mut db = Database::new()?;
-        let bans = db.get_bans()?;
-        let nicer_bans: Vec<Value> = bans
-            .iter()
-            .map(|ban| ban.raw_json())
-            .collect();
-        let bans_json = serde_json::to_value(nicer_bans).map_err(|e| {
-            error!(utils::LOGGER, "{}", e);
-            UserError::Internal
-        })?;
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PostBansQuery {
+    partial: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    q: Option<String>,
+    limit: Option<u32>,
+}
+
+/// Matches returned by a search unless `limit` is given.
+const SEARCH_LIMIT_DEFAULT: usize = 50;
+const SEARCH_LIMIT_MAX: usize = 100;
+
+const CATEGORY_NAME_MAX: usize = 64;
+
+/// Bans per page unless `limit` is given.
+const PAGE_SIZE_DEFAULT: usize = 100;
+const PAGE_SIZE_MAX: usize = 1000;
+
+/// Bans fetched from the database at once while exporting.
+const EXPORT_BATCH_SIZE: usize = 1000;
+/// Batches kept for a slow client before the export waits for it.
+const EXPORT_BUFFERED_BATCHES: usize = 4;
+
+/// Serializations of the banlist offered by `get_bans`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    /// A single array of bans
+    Json,
+    /// One ban per line
+    Ndjson,
+    /// With a header row. Timestamps are Unix timestamps like everywhere else
+    Csv,
+}
+
+impl ExportFormat {
+    /// The `format` query parameter takes precedence over the `Accept` header. Defaults to JSON.
+    fn negotiate(req: &HttpRequest, format: &Option<String>) -> Result<Self, UserError> {
+        match format.as_deref() {
+            Some("json") => return Ok(ExportFormat::Json),
+            Some("ndjson") => return Ok(ExportFormat::Ndjson),
+            Some("csv") => return Ok(ExportFormat::Csv),
+            Some(_) => return Err(UserError::BadRequest("format has to be `json`, `ndjson` or `csv`")),
+            None => {}
+        }
+        let accept = req.headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        // The first known media type wins, quality values are ignored
+        for media_type in accept.split(',').map(|t| t.split(';').next().unwrap_or_default().trim()) {
+            match media_type {
+                "application/json" => return Ok(ExportFormat::Json),
+                "application/x-ndjson" | "application/ndjson" => return Ok(ExportFormat::Ndjson),
+                "text/csv" => return Ok(ExportFormat::Csv),
+                _ => {}
+            }
+        }
+        Ok(ExportFormat::Json)
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "[",
+            ExportFormat::Ndjson => "",
+            ExportFormat::Csv => "id,reason,date,admin,message,expires,category\r\n",
+        }
+    }
+
+    fn footer(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "]",
+            _ => "",
+        }
+    }
+
+    /// Serializes a batch of bans. `first` is whether it's the first batch of the export.
+    fn encode(self, bans: &[Ban], first: bool) -> Result<Bytes, UserError> {
+        let mut buf = Vec::new();
+        for (i, ban) in bans.iter().map(Ban::to_api).enumerate() {
+            match self {
+                ExportFormat::Json => {
+                    if !first || i > 0 {
+                        buf.push(b',');
+                    }
+                    serde_json::to_writer(&mut buf, &ban).map_err(|e| {
+                        error!(utils::LOGGER, "{}", e);
+                        UserError::Internal
+                    })?;
+                }
+                ExportFormat::Ndjson => {
+                    serde_json::to_writer(&mut buf, &ban).map_err(|e| {
+                        error!(utils::LOGGER, "{}", e);
+                        UserError::Internal
+                    })?;
+                    buf.push(b'\n');
+                }
+                ExportFormat::Csv => {
+                    let line = format!("{},{},{},{},{},{},{}\r\n",
+                                       ban.id,
+                                       csv_field(&ban.reason),
+                                       ban.date,
+                                       ban.admin,
+                                       csv_field(ban.message.as_deref().unwrap_or_default()),
+                                       ban.expires.map(|e| e.to_string()).unwrap_or_default(),
+                                       ban.category.map(|c| c.to_string()).unwrap_or_default());
+                    buf.extend_from_slice(line.as_bytes());
+                }
+            }
+        }
+        Ok(Bytes::from(buf))
+    }
+}
 
-        Ok(HttpResponse::Ok().json(bans_json))
+/// Quotes the field if it contains a comma, quote or line break, as RFC 4180 requires.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
     } else {
-        Err(UserError::Forbidden)
+        field.to_string()
     }
 }
 
+fn to_micros(time: NaiveDateTime) -> i64 {
+    time.timestamp() * 1_000_000 + i64::from(time.timestamp_subsec_micros())
+}
+
+fn from_micros(micros: i64) -> Option<NaiveDateTime> {
+    NaiveDateTime::from_timestamp_opt(micros.div_euclid(1_000_000),
+                                      micros.rem_euclid(1_000_000) as u32 * 1000)
+}
+
+/// Cursors of delta syncs are prefixed, so the timestamps that were used before are rejected.
+fn encode_cursor(cursor: i64) -> String {
+    format!("c{}", cursor)
+}
+
+fn decode_cursor(cursor: &str) -> Option<i64> {
+    cursor.strip_prefix('c')?.parse().ok()
+}
+
+/// Position of the last ban on a page: its ID, prefixed with its date when sorting by date.
+fn encode_page_cursor(ban: &Ban, sort: BanSort) -> String {
+    match sort {
+        BanSort::Id => ban.id.to_string(),
+        BanSort::Date => format!("{}_{}", to_micros(ban.date), ban.id),
+    }
+}
+
+fn decode_page_cursor(cursor: &str, sort: BanSort) -> Option<(Option<NaiveDateTime>, Option<i64>)> {
+    match sort {
+        BanSort::Id => Some((None, Some(cursor.parse().ok()?))),
+        BanSort::Date => {
+            let (micros, id) = cursor.split_once('_')?;
+            Some((Some(from_micros(micros.parse().ok()?)?), Some(id.parse().ok()?)))
+        }
+    }
+}
+
+pub fn get_bans(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    query: web::Query<ExportQuery>,
+    page: web::Query<api::BanListQuery>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    // Paging through the banlist reads just as much of it as an export, so both are gated the same
+    if !(guard.root() || (guard.admin() && settings::ENV.general.admin_export)) {
+        return Err(UserError::Forbidden);
+    }
+    if *page != api::BanListQuery::default() {
+        if query.format.is_some() {
+            return Err(UserError::BadRequest("pages are always JSON, `format` can only be used to export"));
+        }
+        let ban_query = ban_query(&page)?;
+        let mut bans = guard.db.get_bans_page(&BanQuery { limit: ban_query.limit + 1, ..ban_query.clone() })?;
+        // The extra ban only tells whether there's another page
+        let cursor = if bans.len() > ban_query.limit {
+            bans.truncate(ban_query.limit);
+            bans.last().map(|ban| encode_page_cursor(ban, ban_query.sort))
+        } else {
+            None
+        };
+        let next = cursor.as_ref().map(|cursor| {
+            let after = format!("after={}", cursor);
+            let mut params: Vec<&str> = req.query_string()
+                .split('&')
+                .filter(|param| !param.is_empty() && !param.starts_with("after="))
+                .collect();
+            params.push(&after);
+            format!("{}?{}", req.path(), params.join("&"))
+        });
+
+        let mut response = HttpResponse::Ok();
+        if let Some(next) = &next {
+            response.header(header::LINK, format!("<{}>; rel=\"next\"", next));
+        }
+        Ok(response.json(api::BanPage {
+            bans: bans.iter().map(Ban::to_api).collect(),
+            cursor,
+            next,
+        }))
+    } else {
+        let format = ExportFormat::negotiate(&req, &query.format)?;
+        let (sender, receiver) = mpsc::channel(EXPORT_BUFFERED_BATCHES);
+        let pool = pool.get_ref().clone();
+        thread::spawn(move || export(&pool, format, sender));
+
+        Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .streaming(receiver.map_err(|()| UserError::Internal).and_then(|chunk| chunk)))
+    }
+}
+
+fn ban_query(page: &api::BanListQuery) -> Result<BanQuery, UserError> {
+    let limit = match page.limit {
+        Some(limit) if limit == 0 || limit as usize > PAGE_SIZE_MAX => {
+            return Err(UserError::BadRequest("limit has to be between 1 and 1000"));
+        }
+        Some(limit) => limit as usize,
+        None => PAGE_SIZE_DEFAULT,
+    };
+    let timestamp = |timestamp: Option<i64>| match timestamp {
+        Some(timestamp) => NaiveDateTime::from_timestamp_opt(timestamp, 0)
+            .map(Some)
+            .ok_or(UserError::BadRequest("invalid timestamp")),
+        None => Ok(None),
+    };
+    let sort = page.sort.unwrap_or(BanSort::Id);
+    let (after_date, after_id) = match &page.after {
+        Some(cursor) => decode_page_cursor(cursor, sort).ok_or(UserError::BadRequest("invalid cursor"))?,
+        None => (None, None),
+    };
+    Ok(BanQuery {
+        since: timestamp(page.since)?,
+        until: timestamp(page.until)?,
+        admin: page.admin,
+        reason: page.reason.clone(),
+        has_message: page.has_message,
+        category: page.category,
+        sort,
+        descending: page.order == Some(api::SortOrder::Desc),
+        after_id,
+        after_date,
+        limit,
+    })
+}
+
+/// Writes the banlist into `sender` until it's done or the client went away. Runs on its own thread,
+/// since the database is blocking. The channel is bounded, so a slow client pauses the export.
+fn export(pool: &Pool, format: ExportFormat, sender: mpsc::Sender<Result<Bytes, UserError>>) {
+    let mut sink = sender.wait();
+    // Empty chunks would end the response early
+    let header = format.header();
+    if !header.is_empty() && sink.send(Ok(Bytes::from_static(header.as_bytes()))).is_err() {
+        return;
+    }
+    let mut first = true;
+    let result = pool.get().and_then(|mut db| db.export_bans(EXPORT_BATCH_SIZE, &mut |bans| {
+        let chunk = format.encode(&bans, first);
+        first = false;
+        sink.send(chunk).is_ok()
+    }));
+    let last = match result {
+        Ok(()) if format.footer().is_empty() => return,
+        Ok(()) => Ok(Bytes::from_static(format.footer().as_bytes())),
+        Err(e) => {
+            error!(utils::LOGGER, "{}", e);
+            // Aborts the response, so the client can't mistake it for the whole banlist
+            Err(UserError::Internal)
+        }
+    };
+    let _ = sink.send(last).and_then(|()| sink.flush());
+}
+
+/// Adds all bans in one transaction, so either all or none of them are stored. With `partial=true`
+/// invalid bans are skipped instead, and the outcome of each ban is returned.
 pub fn post_bans(
     req: HttpRequest,
-    data: web::Json<Vec<CreateBan>>,
+    pool: web::Data<Pool>,
+    query: web::Query<PostBansQuery>,
+    data: web::Json<Vec<api::CreateBan>>,
 ) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+    let mut guard = TokenGuard::new(&pool, &req)?;
     if guard.admin() {
-        let mut db = Database::new()?;
-        for ban in data.iter() {
-            if !ban.reason.is_empty() {
-                db.add_ban(ban.id,
-                           &ban.reason,
-                           guard.token.id,
-                           &ban.message)?;
-            } else {
-                return Err(UserError::BadRequest("ban reason can not be empty"));
-            }
+        let now = Utc::now().timestamp();
+        let categories = if data.iter().any(|ban| ban.category.is_some()) {
+            guard.db.get_ban_categories()?
+        } else {
+            Vec::new()
+        };
+        let checked: Vec<Result<NewBan, &'static str>> = data.iter()
+            .map(|ban| NewBan::from_api(ban, &categories, now))
+            .collect();
+        if !query.partial.unwrap_or(false) {
+            let bans = checked.into_iter().collect::<Result<Vec<NewBan>, _>>().map_err(UserError::BadRequest)?;
+            guard.db.add_bans(&bans, guard.token.id)?;
+            return Ok(HttpResponse::NoContent().body(""));
         }
-        Ok(HttpResponse::NoContent().body(""))
+
+        let valid: Vec<NewBan> = checked.iter().filter_map(|ban| ban.as_ref().ok()).cloned().collect();
+        let mut stored = guard.db.add_bans_partial(&valid, guard.token.id)?.into_iter();
+        let results = data.iter()
+            .zip(checked)
+            .map(|(ban, checked)| {
+                let (status, error) = match checked.map(|_| stored.next()) {
+                    Ok(Some(Ok(BanAction::Update))) => (api::BanStatus::Updated, None),
+                    Ok(Some(Ok(_))) => (api::BanStatus::Created, None),
+                    Ok(Some(Err(e))) => {
+                        error!(utils::LOGGER, "Could not store ban"; "id" => ban.id, "error" => e.to_string());
+                        (api::BanStatus::Error, Some("could not be stored".to_string()))
+                    }
+                    // Every valid ban has a result, anything else is a bug in the backend
+                    Ok(None) => return Err(UserError::Internal),
+                    Err(error) => (api::BanStatus::Error, Some(error.to_string())),
+                };
+                Ok(api::BanResult { id: ban.id, status, error })
+            })
+            .collect::<Result<Vec<api::BanResult>, UserError>>()?;
+        Ok(HttpResponse::Ok().json(results))
     } else {
         Err(UserError::Forbidden)
     }
 }
 
-pub fn get_ban(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let _guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+pub fn get_ban(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
     let user_id: i64 = req.match_info().get("id").unwrap().parse().map_err(|_| {
         UserError::BadRequest("could not convert user id to integer")
     })?;
-    let mut db = Database::new()?;
-    match db.get_ban(user_id)? {
+    match guard.db.get_ban(user_id)? {
         Some(ban) => Ok(HttpResponse::Ok().json(ban.json()?)),
         None => Err(UserError::NotFound),
     }
 }
 
-pub fn delete_ban(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+pub fn check_bans(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    data: web::Json<Vec<i64>>,
+) -> Result<HttpResponse, UserError> {
+    // Rejected before the guard so an oversized request doesn't use up a token of the bucket
+    if data.len() > settings::ENV.general.max_check_size {
+        return Err(UserError::BadRequest("too many user ids in one request"));
+    }
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    let mut user_ids = data.into_inner();
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    let bans = guard.db.get_bans_by_ids(&user_ids)?;
+    let nicer_bans: Vec<Value> = bans
+        .iter()
+        .map(|ban| ban.raw_json())
+        .collect();
+
+    Ok(HttpResponse::Ok().json(nicer_bans))
+}
+
+pub fn delete_ban(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
     if guard.admin() {
         let user_id: i64 = req.match_info().get("id").unwrap().parse().map_err(|_| {
             UserError::BadRequest("could not convert id to integer")
         })?;
 
-        let mut db = Database::new()?;
-
-        match db.get_ban(user_id)? {
+        match guard.db.get_ban(user_id)? {
             Some(_) => {
-                db.delete_ban(user_id)?;
+                guard.db.delete_ban(user_id, guard.token.id)?;
                 Ok(HttpResponse::NoContent().body(""))
             }
             None => Err(UserError::NotFound),
@@ -90,11 +414,57 @@ pub fn delete_ban(req: HttpRequest) -> Result<HttpResponse, UserError> {
     }
 }
 
-pub fn get_bans_id_list(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let mut guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
-    guard.banlist_all()?;
-    let mut db = Database::new()?;
-    let bans = db.get_banned_ids()?;
+pub fn get_ban_history(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    if guard.admin() {
+        let user_id: i64 = req.match_info().get("id").unwrap().parse().map_err(|_| {
+            UserError::BadRequest("could not convert user id to integer")
+        })?;
+        let history: Vec<Value> = guard.db
+            .get_ban_history(user_id)?
+            .iter()
+            .map(|entry| entry.raw_json())
+            .collect();
+
+        Ok(HttpResponse::Ok().json(history))
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn search_bans(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    query: web::Query<SearchQuery>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    if guard.admin() {
+        let search = match query.q.as_deref().map(str::trim) {
+            Some(search) if !search.is_empty() => search,
+            _ => return Err(UserError::BadRequest("`q` is required")),
+        };
+        let limit = match query.limit {
+            Some(limit) if limit == 0 || limit as usize > SEARCH_LIMIT_MAX => {
+                return Err(UserError::BadRequest("limit has to be between 1 and 100"));
+            }
+            Some(limit) => limit as usize,
+            None => SEARCH_LIMIT_DEFAULT,
+        };
+        let matches: Vec<api::BanMatch> = guard.db
+            .search_bans(search, limit)?
+            .iter()
+            .map(BanMatch::to_api)
+            .collect();
+
+        Ok(HttpResponse::Ok().json(matches))
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn get_bans_id_list(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    let bans = guard.db.get_banned_ids()?;
     let nicer_bans: Vec<&i64> = bans
         .iter()
         .collect();
@@ -102,3 +472,110 @@ pub fn get_bans_id_list(req: HttpRequest) -> Result<HttpResponse, UserError> {
 
     Ok(HttpResponse::Ok().body(response.join("\n")))
 }
+
+pub fn get_ban_changes(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    query: web::Query<ChangesQuery>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    let since = match (&query.since, &query.cursor) {
+        (_, Some(cursor)) => ChangesSince::Cursor(decode_cursor(cursor).ok_or(UserError::BadRequest("invalid cursor"))?),
+        (Some(since), None) => ChangesSince::Time(NaiveDateTime::from_timestamp_opt(*since, 0)
+            .ok_or(UserError::BadRequest("invalid timestamp"))?),
+        (None, None) => return Err(UserError::BadRequest("either `since` or `cursor` is required")),
+    };
+    let changes = guard.db.get_ban_changes(since)?;
+
+    Ok(HttpResponse::Ok().json(api::BanChanges {
+        added: changes.added,
+        removed: changes.removed,
+        cursor: encode_cursor(changes.cursor),
+    }))
+}
+
+/// Category names are compared in lowercase, so `Spam` and `spam` can't both exist.
+fn category_name(name: &str) -> Result<String, UserError> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name.len() > CATEGORY_NAME_MAX
+        || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_') {
+        return Err(UserError::BadRequest("category name has to be 1 to 64 letters, digits, `-` or `_`"));
+    }
+    Ok(name)
+}
+
+fn category_id(req: &HttpRequest) -> Result<i32, UserError> {
+    req.match_info().get("id").unwrap().parse().map_err(|_| {
+        UserError::BadRequest("could not convert category id to integer")
+    })
+}
+
+pub fn get_ban_categories(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    let categories: Vec<api::BanCategory> = guard.db.get_ban_categories()?.iter().map(BanCategory::to_api).collect();
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+pub fn post_ban_categories(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    data: web::Json<api::CreateBanCategory>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    if guard.root() {
+        let name = category_name(&data.name)?;
+        if guard.db.get_ban_categories()?.iter().any(|category| category.name == name) {
+            return Err(UserError::Conflict("a category with this name exists already"));
+        }
+        let category = guard.db.create_ban_category(&name, &data.description)?;
+        info!(utils::LOGGER, "Created ban category"; "id" => category.id, "name" => &category.name);
+        Ok(HttpResponse::Created().json(category.to_api()))
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn put_ban_category(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    data: web::Json<api::CreateBanCategory>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    if guard.root() {
+        let category_id = category_id(&req)?;
+        let name = category_name(&data.name)?;
+        let categories = guard.db.get_ban_categories()?;
+        if !categories.iter().any(|category| category.id == category_id) {
+            return Err(UserError::NotFound);
+        }
+        if categories.iter().any(|category| category.name == name && category.id != category_id) {
+            return Err(UserError::Conflict("a category with this name exists already"));
+        }
+        let category = BanCategory {
+            id: category_id,
+            name,
+            description: data.description.clone(),
+        };
+        guard.db.update_ban_category(&category)?;
+        Ok(HttpResponse::Ok().json(category.to_api()))
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn delete_ban_category(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+    if guard.root() {
+        let category_id = category_id(&req)?;
+        if guard.db.get_ban_category(category_id)?.is_none() {
+            return Err(UserError::NotFound);
+        }
+        if guard.db.delete_ban_category(category_id)? {
+            Ok(HttpResponse::NoContent().body(""))
+        } else {
+            Err(UserError::Conflict("the category is still used by bans"))
+        }
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
@@ -1,3 +1,142 @@
+use std::collections::HashMap;
+
+use actix_web::dev::{Factory, ResourceDef};
+use actix_web::http::Method;
+use actix_web::{FromRequest, HttpRequest, Resource, Responder, Route, web};
+use lazy_static::lazy_static;
+
 pub mod banlist;
+pub mod openapi;
 pub mod root;
 pub mod tokens;
+
+/// A single method of a resource. `openapi::spec` documents each of them.
+pub struct Endpoint {
+    pub method: Method,
+    pub path: &'static str,
+    /// Name of the rate limit in `settings::RateLimitCfg` that applies to it
+    pub limit: &'static str,
+    route: Route,
+}
+
+impl Endpoint {
+    fn limit(mut self, name: &'static str) -> Endpoint {
+        self.limit = name;
+        self
+    }
+}
+
+fn endpoint<F, T, R>(method: Method, path: &'static str, handler: F) -> Endpoint
+    where
+        F: Factory<T, R> + 'static,
+        T: FromRequest + 'static,
+        R: Responder + 'static,
+{
+    Endpoint {
+        route: web::method(method.clone()).to(handler),
+        method,
+        path,
+        limit: "default",
+    }
+}
+
+/// Every endpoint of the API. Resources are matched in this order,
+/// so fixed paths like `/tokens/inactive` have to come before `/tokens/{id}`.
+pub fn endpoints() -> Vec<Endpoint> {
+    vec![
+        endpoint(Method::GET, "/", root::info),
+        endpoint(Method::HEAD, "/", root::info),
+        endpoint(Method::GET, "/version", root::version),
+        endpoint(Method::GET, "/health", root::health),
+        endpoint(Method::GET, "/ready", root::ready),
+        endpoint(Method::GET, "/stats", root::stats),
+        endpoint(Method::GET, "/openapi.json", openapi::openapi),
+        endpoint(Method::GET, "/tokens", tokens::get_tokens),
+        endpoint(Method::POST, "/tokens", tokens::post_tokens),
+        endpoint(Method::GET, "/tokens/inactive", tokens::get_inactive_tokens),
+        endpoint(Method::GET, "/tokens/{id}", tokens::get_token),
+        endpoint(Method::DELETE, "/tokens/{id}", tokens::delete_token),
+        endpoint(Method::GET, "/tokens/{id}/limits", tokens::get_token_limits),
+        endpoint(Method::PUT, "/tokens/{id}/limits", tokens::put_token_limits),
+        endpoint(Method::DELETE, "/tokens/{id}/limits", tokens::delete_token_limits),
+        endpoint(Method::GET, "/tokens/userid/{uid}", tokens::get_token_by_userid),
+        endpoint(Method::GET, "/banlist", banlist::get_bans),
+        endpoint(Method::POST, "/banlist", banlist::post_bans),
+        // Expensive, so it has its own limit
+        endpoint(Method::GET, "/banlist/all", banlist::get_bans_id_list).limit("banlist_all"),
+        endpoint(Method::GET, "/banlist/changes", banlist::get_ban_changes),
+        endpoint(Method::POST, "/banlist/check", banlist::check_bans),
+        endpoint(Method::GET, "/banlist/search", banlist::search_bans),
+        endpoint(Method::GET, "/banlist/categories", banlist::get_ban_categories),
+        endpoint(Method::POST, "/banlist/categories", banlist::post_ban_categories),
+        endpoint(Method::PUT, "/banlist/categories/{id}", banlist::put_ban_category),
+        endpoint(Method::DELETE, "/banlist/categories/{id}", banlist::delete_ban_category),
+        endpoint(Method::GET, "/banlist/{id}", banlist::get_ban),
+        endpoint(Method::DELETE, "/banlist/{id}", banlist::delete_ban),
+        endpoint(Method::GET, "/banlist/{id}/history", banlist::get_ban_history),
+    ]
+}
+
+/// Served outside of `endpoints`, on its own address unless `metrics.bind` is empty.
+pub const METRICS_PATH: &str = "/metrics";
+
+thread_local! {
+    /// Paths of all resources, in the order the router tries them. Per thread since `ResourceDef`
+    /// isn't `Sync`, like the router itself, which every worker builds for its own thread.
+    static RESOURCES: Vec<(&'static str, ResourceDef)> = {
+        let mut paths = vec![METRICS_PATH];
+        for endpoint in endpoints() {
+            if !paths.contains(&endpoint.path) {
+                paths.push(endpoint.path);
+            }
+        }
+        paths.into_iter().map(|path| (path, ResourceDef::new(path))).collect()
+    };
+}
+
+/// Path of the resource the request was routed to, e.g. `/banlist/{id}`, `None` if there was none.
+///
+/// This is matched against the path the router saw, in which percent-encoded characters are
+/// decoded, so `/banlist/%61ll` resolves to `/banlist/all` just like the router does.
+pub fn resource(req: &HttpRequest) -> Option<&'static str> {
+    let path = req.match_info().get_ref().path();
+    RESOURCES.with(|resources| {
+        resources
+            .iter()
+            .find(|(_, resource)| resource.is_match(path))
+            .map(|(path, _)| *path)
+    })
+}
+
+lazy_static! {
+    /// Rate limit of each endpoint, by method and path.
+    static ref LIMITS: HashMap<(Method, &'static str), &'static str> = endpoints()
+        .into_iter()
+        .map(|endpoint| ((endpoint.method, endpoint.path), endpoint.limit))
+        .collect();
+}
+
+/// Name of the rate limit of the endpoint the request was routed to.
+pub fn limit_name(req: &HttpRequest) -> &'static str {
+    resource(req)
+        .and_then(|path| LIMITS.get(&(req.method().clone(), path)))
+        .copied()
+        .unwrap_or("default")
+}
+
+/// Registers all endpoints, with one resource per path.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    let mut resources: Vec<(&str, Resource)> = Vec::new();
+    for endpoint in endpoints() {
+        match resources.iter().position(|(path, _)| *path == endpoint.path) {
+            Some(i) => {
+                let (path, resource) = resources.remove(i);
+                resources.insert(i, (path, resource.route(endpoint.route)));
+            }
+            None => resources.push((endpoint.path, web::resource(endpoint.path).route(endpoint.route))),
+        }
+    }
+    for (_, resource) in resources {
+        cfg.service(resource);
+    }
+}
@@ -0,0 +1,500 @@
+use actix_web::http::Method;
+use actix_web::HttpResponse;
+use lazy_static::lazy_static;
+use serde_json::{json, Map, Value};
+
+use crate::routes;
+
+lazy_static! {
+    static ref SPEC: Value = spec();
+}
+
+pub fn openapi() -> HttpResponse {
+    HttpResponse::Ok().json(&*SPEC)
+}
+
+/// OpenAPI 3 document of every endpoint in `routes::endpoints`.
+pub fn spec() -> Value {
+    let mut paths = Map::new();
+    for endpoint in routes::endpoints() {
+        if let Some(operation) = operation(&endpoint.method, endpoint.path) {
+            let path = paths.entry(endpoint.path).or_insert_with(|| json!({}));
+            path[endpoint.method.as_str().to_lowercase()] = operation.0;
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": env!("CARGO_PKG_DESCRIPTION")
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "token": {
+                    "type": "http",
+                    "scheme": "bearer"
+                }
+            },
+            "responses": {
+                "BadRequest": error_response("The request is malformed or invalid"),
+                "Unauthorized": error_response("The token is missing, unknown, retired or expired"),
+                "Forbidden": error_response("The token doesn't have the required permission"),
+                "NotFound": error_response("The resource doesn't exist"),
+                "Conflict": error_response("The request conflicts with the current state"),
+                "TooManyRequests": {
+                    "description": "The rate limit of the token was exceeded",
+                    "headers": {
+                        "Retry-After": {
+                            "description": "Seconds until the next request is allowed",
+                            "schema": {"type": "integer"}
+                        }
+                    },
+                    "content": json_content(reference("Error"))
+                }
+            },
+            "schemas": schemas()
+        }
+    })
+}
+
+/// Documentation of a single endpoint, `None` if it's undocumented.
+fn operation(method: &Method, path: &str) -> Option<Operation> {
+    let op = match (method.as_str(), path) {
+        ("GET", "/") | ("HEAD", "/") => Operation::public("Information about this instance")
+            .content("200", "HTML page with links to the documentation", "text/html", json!({"type": "string"})),
+        ("GET", "/version") => Operation::public("Version of the API")
+            .response("200", "The version", reference("Version")),
+        ("GET", "/health") => Operation::public("Liveness check")
+            .response("200", "The process is running", reference("Health")),
+        ("GET", "/ready") => Operation::public("Readiness check")
+            .response("200", "Ready to serve requests", reference("Ready"))
+            .response("503", "A check failed", reference("Ready")),
+        ("GET", "/stats") => Operation::public("Statistics about the banlist")
+            .response("200", "The statistics", reference("Stats")),
+        ("GET", "/openapi.json") => Operation::public("This document")
+            .response("200", "OpenAPI 3 document", json!({"type": "object"})),
+        ("GET", "/tokens") => Operation::authenticated("List all tokens", "Root")
+            .response("200", "All tokens", array(reference("Token"))),
+        ("POST", "/tokens") => Operation::authenticated("Create a token", "Root")
+            .body(reference("CreateToken"))
+            .response("201", "The token, including its secret. It is never returned again", reference("CreatedToken"))
+            .error("400", "BadRequest"),
+        ("GET", "/tokens/inactive") => Operation::authenticated("List tokens that weren't used recently", "Root")
+            .param("since", "query", json!({"type": "integer", "format": "int64"}),
+                   "Unix timestamp, defaults to 30 days ago")
+            .response("200", "Tokens neither used nor created since then", array(reference("Token")))
+            .error("400", "BadRequest"),
+        ("GET", "/tokens/{id}") => Operation::authenticated("Get a token and its usage", "User")
+            .param("id", "path", json!({"type": "string"}),
+                   "ID of the token, or `self` for the token making the request. Other tokens require Root")
+            .response("200", "The token", reference("TokenDetails"))
+            .error("400", "BadRequest")
+            .error("403", "Forbidden")
+            .error("404", "NotFound"),
+        ("DELETE", "/tokens/{id}") => Operation::authenticated("Retire a token", "Root")
+            .param("id", "path", json!({"type": "integer", "format": "int32"}), "ID of the token")
+            .no_content("The token was retired")
+            .error("400", "BadRequest")
+            .error("404", "NotFound"),
+        ("GET", "/tokens/{id}/limits") => Operation::authenticated("Get the rate limits of a token", "Root")
+            .param("id", "path", json!({"type": "integer", "format": "int32"}), "ID of the token")
+            .response("200", "Limits and current buckets", reference("TokenLimits"))
+            .error("400", "BadRequest")
+            .error("404", "NotFound"),
+        ("PUT", "/tokens/{id}/limits") => Operation::authenticated("Override rate limits of a token", "Root")
+            .param("id", "path", json!({"type": "integer", "format": "int32"}), "ID of the token")
+            .body(json!({
+                "type": "object",
+                "description": "Overrides by limit name. Limits that are left out use the config again",
+                "additionalProperties": reference("Limit")
+            }))
+            .no_content("The overrides were replaced")
+            .error("400", "BadRequest")
+            .error("404", "NotFound"),
+        ("DELETE", "/tokens/{id}/limits") => Operation::authenticated("Reset the limits of a token to the defaults", "Root")
+            .param("id", "path", json!({"type": "integer", "format": "int32"}), "ID of the token")
+            .no_content("The buckets were refilled and the overrides removed")
+            .error("400", "BadRequest")
+            .error("404", "NotFound"),
+        ("GET", "/tokens/userid/{uid}") => Operation::authenticated("List the tokens of a user", "Root")
+            .param("uid", "path", json!({"type": "integer", "format": "int64"}), "Telegram ID of the user")
+            .response("200", "Tokens of the user", array(reference("Token")))
+            .error("400", "BadRequest"),
+        ("GET", "/banlist") => Operation::authenticated("Browse or export bans", "Root")
+            .describe("Without any of the paging or filter parameters the whole banlist is exported. \
+                       Admin tokens may do either only if `admin_export` is enabled.")
+            .param("format", "query", json!({"type": "string", "enum": ["json", "ndjson", "csv"]}),
+                   "Format of the export. Takes precedence over the `Accept` header. Defaults to `json`")
+            .param("limit", "query", json!({"type": "integer", "minimum": 1, "maximum": 1000}),
+                   "Bans per page, defaults to 100")
+            .param("after", "query", json!({"type": "string"}), "`cursor` of the previous page")
+            .param("since", "query", json!({"type": "integer", "format": "int64"}),
+                   "Only bans from this Unix timestamp on")
+            .param("until", "query", json!({"type": "integer", "format": "int64"}),
+                   "Only bans from before this Unix timestamp")
+            .param("admin", "query", json!({"type": "integer", "format": "int32"}),
+                   "Only bans added by this token")
+            .param("reason", "query", json!({"type": "string"}), "Only bans whose reason contains this, ignoring case")
+            .param("has_message", "query", json!({"type": "boolean"}), "Only bans with or without a message")
+            .param("category", "query", json!({"type": "integer", "format": "int32"}), "Only bans of this category")
+            .param("sort", "query", json!({"type": "string", "enum": ["id", "date"]}), "Defaults to `id`")
+            .param("order", "query", json!({"type": "string", "enum": ["asc", "desc"]}), "Defaults to `asc`")
+            .response("200", "A page of bans, or all active bans ordered by ID",
+                      json!({"oneOf": [reference("BanPage"), array(reference("Ban"))]}))
+            .content("200", "A page of bans, or all active bans ordered by ID", "application/x-ndjson", reference("Ban"))
+            .content("200", "A page of bans, or all active bans ordered by ID", "text/csv",
+                     json!({"type": "string", "description": "`id,reason,date,admin,message,expires,category` with a header row"}))
+            .error("400", "BadRequest"),
+        ("POST", "/banlist") => Operation::authenticated("Add or update bans", "Admin")
+            .describe("The reason can be left out when a category is given. Either all bans are added or, \
+                       if one is invalid, none of them, unless `partial` is set.")
+            .param("partial", "query", json!({"type": "boolean"}),
+                   "Skip bans that are invalid or can't be stored instead of rejecting the request, \
+                    and return the outcome of each ban")
+            .body(array(reference("CreateBan")))
+            .response("200", "With `partial`, the outcome of each ban in the order they were sent",
+                      array(reference("BanResult")))
+            .no_content("The bans were added")
+            .error("400", "BadRequest"),
+        ("GET", "/banlist/all") => Operation::authenticated("List all banned IDs", "User")
+            .content("200", "Banned IDs, one per line", "text/plain", json!({"type": "string"})),
+        ("GET", "/banlist/changes") => Operation::authenticated("IDs banned and unbanned since a point in time", "User")
+            .param("since", "query", json!({"type": "integer", "format": "int64"}),
+                   "Unix timestamp. Either this or `cursor` is required")
+            .param("cursor", "query", json!({"type": "string"}), "`cursor` of the previous response")
+            .response("200", "The changes", reference("BanChanges"))
+            .error("400", "BadRequest"),
+        ("POST", "/banlist/check") => Operation::authenticated("Look up several IDs at once", "User")
+            .body(array(json!({"type": "integer", "format": "int64"})))
+            .response("200", "Bans of the IDs that are banned", array(reference("Ban")))
+            .error("400", "BadRequest"),
+        ("GET", "/banlist/search") => Operation::authenticated("Search the reasons and messages of bans", "Admin")
+            .param("q", "query", json!({"type": "string"}),
+                   "Words that all have to occur. Supports `\"quoted phrases\"`, `or` and `-excluded` words")
+            .param("limit", "query", json!({"type": "integer", "minimum": 1, "maximum": 100}),
+                   "Matches to return, defaults to 50")
+            .response("200", "Matches, best first", array(reference("BanMatch")))
+            .error("400", "BadRequest"),
+        ("GET", "/banlist/categories") => Operation::authenticated("List all ban categories", "User")
+            .response("200", "All categories", array(reference("BanCategory"))),
+        ("POST", "/banlist/categories") => Operation::authenticated("Create a ban category", "Root")
+            .body(reference("CreateBanCategory"))
+            .response("201", "The category", reference("BanCategory"))
+            .error("400", "BadRequest")
+            .error("409", "Conflict"),
+        ("PUT", "/banlist/categories/{id}") => Operation::authenticated("Rename or describe a ban category", "Root")
+            .param("id", "path", json!({"type": "integer", "format": "int32"}), "ID of the category")
+            .body(reference("CreateBanCategory"))
+            .response("200", "The category", reference("BanCategory"))
+            .error("400", "BadRequest")
+            .error("404", "NotFound")
+            .error("409", "Conflict"),
+        ("DELETE", "/banlist/categories/{id}") => Operation::authenticated("Delete a ban category", "Root")
+            .describe("Only categories that no ban has, including expired ones, can be deleted.")
+            .param("id", "path", json!({"type": "integer", "format": "int32"}), "ID of the category")
+            .no_content("The category was deleted")
+            .error("400", "BadRequest")
+            .error("404", "NotFound")
+            .error("409", "Conflict"),
+        ("GET", "/banlist/{id}") => Operation::authenticated("Get a ban", "User")
+            .param("id", "path", json!({"type": "integer", "format": "int64"}), "Telegram ID of the user")
+            .response("200", "The ban", reference("Ban"))
+            .error("400", "BadRequest")
+            .error("404", "NotFound"),
+        ("DELETE", "/banlist/{id}") => Operation::authenticated("Remove a ban", "Admin")
+            .param("id", "path", json!({"type": "integer", "format": "int64"}), "Telegram ID of the user")
+            .no_content("The ban was removed")
+            .error("400", "BadRequest")
+            .error("404", "NotFound"),
+        ("GET", "/banlist/{id}/history") => Operation::authenticated("Get every change to a ban", "Admin")
+            .param("id", "path", json!({"type": "integer", "format": "int64"}), "Telegram ID of the user")
+            .response("200", "Changes, oldest first", array(reference("BanHistory")))
+            .error("400", "BadRequest"),
+        _ => return None,
+    };
+    Some(op)
+}
+
+struct Operation(Value);
+
+impl Operation {
+    fn public(summary: &str) -> Self {
+        Operation(json!({
+            "summary": summary,
+            "responses": {}
+        }))
+    }
+
+    /// Endpoints behind `TokenGuard`, which need a token with at least the given permission.
+    fn authenticated(summary: &str, permission: &str) -> Self {
+        let op = Operation(json!({
+            "summary": summary,
+            "description": format!("Requires a token with {} permission.", permission),
+            "security": [{"token": []}],
+            "responses": {}
+        }));
+        let op = op.error("401", "Unauthorized").error("429", "TooManyRequests");
+        if permission == "User" {
+            op
+        } else {
+            op.error("403", "Forbidden")
+        }
+    }
+
+    /// Adds to the description after the required permission.
+    fn describe(mut self, text: &str) -> Self {
+        let description = format!("{} {}", self.0["description"].as_str().unwrap_or_default(), text);
+        self.0["description"] = json!(description.trim_start());
+        self
+    }
+
+    fn param(mut self, name: &str, location: &str, schema: Value, description: &str) -> Self {
+        let params = self.0.as_object_mut().unwrap()
+            .entry("parameters")
+            .or_insert_with(|| json!([]));
+        params.as_array_mut().unwrap().push(json!({
+            "name": name,
+            "in": location,
+            "required": location == "path",
+            "description": description,
+            "schema": schema
+        }));
+        self
+    }
+
+    fn body(mut self, schema: Value) -> Self {
+        self.0["requestBody"] = json!({
+            "required": true,
+            "content": json_content(schema)
+        });
+        self
+    }
+
+    fn response(self, status: &str, description: &str, schema: Value) -> Self {
+        self.content(status, description, "application/json", schema)
+    }
+
+    /// Adds a content type to the response, so it can be called repeatedly for the same status.
+    fn content(mut self, status: &str, description: &str, content_type: &str, schema: Value) -> Self {
+        let response = &mut self.0["responses"][status];
+        response["description"] = json!(description);
+        response["content"][content_type] = json!({"schema": schema});
+        self
+    }
+
+    fn no_content(mut self, description: &str) -> Self {
+        self.0["responses"]["204"] = json!({"description": description});
+        self
+    }
+
+    fn error(mut self, status: &str, response: &str) -> Self {
+        self.0["responses"][status] = json!({"$ref": format!("#/components/responses/{}", response)});
+        self
+    }
+}
+
+fn reference(schema: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", schema)})
+}
+
+fn array(items: Value) -> Value {
+    json!({"type": "array", "items": items})
+}
+
+fn json_content(schema: Value) -> Value {
+    json!({"application/json": {"schema": schema}})
+}
+
+fn error_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": json_content(reference("Error"))
+    })
+}
+
+fn object(required: &[&str], properties: Value) -> Value {
+    json!({
+        "type": "object",
+        "required": required,
+        "properties": properties
+    })
+}
+
+/// Schemas of the request and response bodies. `tests::openapi` checks them against the types.
+fn schemas() -> Value {
+    let timestamp = json!({"type": "integer", "format": "int64", "description": "Unix timestamp"});
+    let optional_timestamp = json!({"type": "integer", "format": "int64", "nullable": true, "description": "Unix timestamp"});
+    let optional_string = json!({"type": "string", "nullable": true});
+    let permission = json!({"type": "string", "enum": ["User", "Admin", "Root"]});
+    let token_properties = json!({
+        "id": {"type": "integer", "format": "int32"},
+        "permission": permission,
+        "userid": {"type": "integer", "format": "int64"},
+        "retired": {"type": "boolean"},
+        "expires_at": optional_timestamp,
+        "name": optional_string,
+        "description": optional_string,
+        "created_at": optional_timestamp,
+        "created_by": {"type": "integer", "format": "int32", "nullable": true},
+        "last_used_at": optional_timestamp
+    });
+    let token_fields = &["id", "permission", "userid", "retired", "expires_at", "name", "description",
+        "created_at", "created_by", "last_used_at"];
+    let with = |extra: Value| {
+        let mut properties = token_properties.clone();
+        properties.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        properties
+    };
+    let ban_properties = json!({
+        "id": {"type": "integer", "format": "int64"},
+        "reason": {"type": "string"},
+        "date": timestamp,
+        "admin": {"type": "integer", "format": "int32", "description": "ID of the token that added the ban"},
+        "message": optional_string,
+        "expires": optional_timestamp,
+        "category": {"type": "integer", "format": "int32", "nullable": true,
+                     "description": "ID of the category, `reason` is free-text detail then"}
+    });
+    let ban_fields = &["id", "reason", "date", "admin", "message", "expires", "category"];
+    let with_ban = |extra: Value| {
+        let mut properties = ban_properties.clone();
+        properties.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        properties
+    };
+    let mut match_fields = ban_fields.to_vec();
+    match_fields.extend(&["rank", "reason_snippet", "message_snippet"]);
+    let mut created_fields = token_fields.to_vec();
+    created_fields.push("token");
+    let mut details_fields = token_fields.to_vec();
+    details_fields.push("usage");
+
+    json!({
+        "Error": object(&["code", "error"], json!({
+            "code": {"type": "integer"},
+            "error": {"type": "string"},
+            "reason": {"type": "string"},
+            "until": timestamp,
+            "request_id": {"type": "string"}
+        })),
+        "Version": object(&["version", "major", "minor", "patch"], json!({
+            "version": {"type": "string"},
+            "major": {"type": "string"},
+            "minor": {"type": "string"},
+            "patch": {"type": "string"}
+        })),
+        "Health": object(&["status"], json!({
+            "status": {"type": "string", "enum": ["ok"]}
+        })),
+        "Ready": object(&["status", "checks"], json!({
+            "status": {"type": "string", "enum": ["ready", "unavailable"]},
+            "checks": {
+                "type": "object",
+                "description": "`ok` or the reason the check failed, by check",
+                "additionalProperties": {"type": "string"}
+            }
+        })),
+        "Stats": object(&["total_ban_count", "categories"], json!({
+            "total_ban_count": {"type": "integer", "format": "int64"},
+            "categories": array(reference("CategoryStats"))
+        })),
+        "CategoryStats": object(&["id", "name", "ban_count"], json!({
+            "id": {"type": "integer", "format": "int32"},
+            "name": {"type": "string"},
+            "ban_count": {"type": "integer", "format": "int64"}
+        })),
+        "Token": object(token_fields, token_properties.clone()),
+        "CreatedToken": object(&created_fields, with(json!({
+            "token": {"type": "string", "description": "The secret"}
+        }))),
+        "TokenDetails": object(&details_fields, with(json!({
+            "usage": array(reference("TokenUsage"))
+        }))),
+        "TokenUsage": object(&["endpoint", "requests", "last_used_at"], json!({
+            "endpoint": {"type": "string", "example": "GET /banlist/{id}"},
+            "requests": {"type": "integer", "format": "int64"},
+            "last_used_at": timestamp
+        })),
+        "CreateToken": object(&["id", "permission"], json!({
+            "id": {"type": "integer", "format": "int64", "description": "Telegram ID of the user the token is for"},
+            "permission": permission,
+            "expires_at": {"type": "integer", "format": "int64", "description": "Unix timestamp, defaults to the configured lifetime"},
+            "name": {"type": "string", "maxLength": 64},
+            "description": {"type": "string"}
+        })),
+        "Limit": object(&["burst", "period"], json!({
+            "burst": {"type": "integer", "description": "Requests allowed at once, 0 for unlimited"},
+            "period": {"type": "integer", "description": "Seconds it takes to refill all of them"}
+        })),
+        "TokenLimits": object(&["limits", "buckets"], json!({
+            "limits": {
+                "type": "object",
+                "additionalProperties": {
+                    "allOf": [reference("Limit")],
+                    "properties": {"overridden": {"type": "boolean"}}
+                }
+            },
+            "buckets": array(reference("Bucket"))
+        })),
+        "Bucket": object(&["endpoint", "available", "updated_at"], json!({
+            "endpoint": {"type": "string"},
+            "available": {"type": "number"},
+            "updated_at": timestamp
+        })),
+        "Ban": object(ban_fields, ban_properties.clone()),
+        "BanMatch": object(&match_fields, with_ban(json!({
+            "rank": {"type": "number", "format": "float", "description": "Relevance, higher is better"},
+            "reason_snippet": {"type": "string", "description": "The reason as HTML, with matching words in `<mark>` tags"},
+            "message_snippet": {"type": "string", "nullable": true,
+                                "description": "The best matching parts of the message, like `reason_snippet`"}
+        }))),
+        "CreateBan": object(&["id"], json!({
+            "id": {"type": "integer", "format": "int64"},
+            "reason": {"type": "string", "description": "Required unless `category` is given"},
+            "message": optional_string,
+            "expires": {"type": "integer", "format": "int64", "nullable": true, "description": "Unix timestamp, permanent if left out"},
+            "category": {"type": "integer", "format": "int32", "description": "ID of an existing category"}
+        })),
+        "BanResult": object(&["id", "status"], json!({
+            "id": {"type": "integer", "format": "int64"},
+            "status": {"type": "string", "enum": ["created", "updated", "error"]},
+            "error": {"type": "string", "description": "Why the ban was skipped"}
+        })),
+        "BanCategory": object(&["id", "name", "description"], json!({
+            "id": {"type": "integer", "format": "int32"},
+            "name": {"type": "string", "pattern": "^[a-z0-9_-]{1,64}$"},
+            "description": optional_string
+        })),
+        "CreateBanCategory": object(&["name"], json!({
+            "name": {"type": "string", "maxLength": 64,
+                     "description": "Letters, digits, `-` and `_`. Stored in lowercase"},
+            "description": {"type": "string"}
+        })),
+        "BanPage": object(&["bans", "cursor", "next"], json!({
+            "bans": array(reference("Ban")),
+            "cursor": {"type": "string", "nullable": true,
+                       "description": "Pass as `after` to get the next page, null on the last page"},
+            "next": {"type": "string", "nullable": true, "description": "Path of the next page with the same filters"}
+        })),
+        "BanHistory": object(&["id", "ban_id", "action", "token", "old_reason", "new_reason",
+            "old_message", "new_message", "date"], json!({
+            "id": {"type": "integer", "format": "int32"},
+            "ban_id": {"type": "integer", "format": "int64"},
+            "action": {"type": "string", "enum": ["Create", "Update", "Delete", "Expire"]},
+            "token": {"type": "integer", "format": "int32", "nullable": true},
+            "old_reason": optional_string,
+            "new_reason": optional_string,
+            "old_message": optional_string,
+            "new_message": optional_string,
+            "date": timestamp
+        })),
+        "BanChanges": object(&["added", "removed", "cursor"], json!({
+            "added": array(json!({"type": "integer", "format": "int64"})),
+            "removed": array(json!({"type": "integer", "format": "int64"})),
+            "cursor": {"type": "string", "description": "Pass as `cursor` to get the changes after these. \
+                                                         Changes close to it may be returned again"}
+        }))
+    })
+}
@@ -1,9 +1,14 @@
-use actix_web::{HttpRequest, HttpResponse};
-use serde_json::json;
+use std::collections::BTreeMap;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use prometheus::{Encoder, TextEncoder};
+use spamwatch_types as api;
 
 use crate::settings;
-use crate::database::Database;
+use crate::database::{migrations, Pool};
 use crate::errors::UserError;
+use crate::metrics;
+use crate::utils;
 
 fn safe_href(name: &str, url: &str) -> String {
     format!(r#"<a rel="noopener" target="_blank" href="{}" class="white-no-dec-link">{}</a>"#, url, name)
@@ -34,19 +39,86 @@ pub fn info() -> HttpResponse {
 }
 
 pub fn version() -> HttpResponse {
-    HttpResponse::Ok().json(json!({
-        "version": &env!("CARGO_PKG_VERSION"),
-        "major": &env!("CARGO_PKG_VERSION_MAJOR"),
-        "minor": &env!("CARGO_PKG_VERSION_MINOR"),
-        "patch": &env!("CARGO_PKG_VERSION_PATCH")
+    HttpResponse::Ok().json(api::Version {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        major: env!("CARGO_PKG_VERSION_MAJOR").to_string(),
+        minor: env!("CARGO_PKG_VERSION_MINOR").to_string(),
+        patch: env!("CARGO_PKG_VERSION_PATCH").to_string(),
+    })
+}
+
+pub fn stats(_req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut db = pool.get()?;
+    let categories = db.get_ban_category_counts()?
+        .into_iter()
+        .map(|(category, ban_count)| api::CategoryStats {
+            id: category.id,
+            name: category.name,
+            ban_count,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(api::Stats {
+        total_ban_count: db.get_total_ban_count()?,
+        categories,
     }))
 }
 
-pub fn stats(_req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let mut db = Database::new()?;
-    let total_ban_count = db.get_total_ban_count()?;
-    let stats = json!({
-        "total_ban_count": total_ban_count
-    });
-    Ok(HttpResponse::Ok().json(serde_json::to_value(stats)?))
+pub fn metrics(_req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut db = pool.get()?;
+    metrics::ACTIVE_TOKENS.set(db.get_active_token_count()?);
+    metrics::BANS.set(db.get_total_ban_count()?);
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut body).map_err(|e| {
+        error!(utils::LOGGER, "{}", e);
+        UserError::Internal
+    })?;
+    Ok(HttpResponse::Ok().content_type(encoder.format_type()).body(body))
+}
+
+/// Liveness: the process is running and answers requests.
+pub fn health() -> HttpResponse {
+    HttpResponse::Ok().json(api::Health {
+        status: "ok".to_string(),
+    })
+}
+
+/// Readiness: the database is reachable, its schema matches this binary and the Genesis Token exists.
+pub fn ready(_req: HttpRequest, pool: web::Data<Pool>) -> HttpResponse {
+    let mut checks = BTreeMap::new();
+    let mut ready = true;
+    let mut check = |name: &str, result: Result<(), String>| {
+        ready &= result.is_ok();
+        checks.insert(name.to_string(), result.err().unwrap_or_else(|| "ok".to_string()));
+    };
+
+    match pool.get() {
+        Ok(mut db) => {
+            check("database", Ok(()));
+            check("schema", match migrations::pending(&mut *db) {
+                Ok(ref pending) if pending.is_empty() => Ok(()),
+                Ok(pending) => Err(format!("{} migration(s) pending", pending.len())),
+                Err(e) => Err(e.to_string()),
+            });
+            if settings::ENV.server.ready_requires_genesis {
+                check("genesis_token", match db.get_token_by_id(1) {
+                    Ok(Some(_)) => Ok(()),
+                    Ok(None) => Err("missing".to_string()),
+                    Err(e) => Err(e.to_string()),
+                });
+            }
+        }
+        Err(e) => check("database", Err(e.to_string())),
+    }
+
+    let body = api::Ready {
+        status: if ready { "ready" } else { "unavailable" }.to_string(),
+        checks,
+    };
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
 }
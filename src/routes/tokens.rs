@@ -1,22 +1,43 @@
+use std::collections::BTreeMap;
+
 use actix_web::{HttpRequest, HttpResponse, Result, web};
+use chrono::{Duration, NaiveDateTime, Utc};
 use serde::Deserialize;
+use spamwatch_types as api;
 
-use crate::database::Database;
+use crate::database::{NewToken, Pool, Storage, Token};
 use crate::errors::UserError;
-use crate::guards::{Permission, TokenGuard};
+use crate::guards::TokenGuard;
+use crate::ratelimit;
+use crate::ratelimit::OverrideCache;
+use crate::settings::Limit;
 use crate::utils;
 
 #[derive(Debug, Deserialize)]
-pub struct CreateToken {
-    id: i64,
-    permission: Permission,
+pub struct InactiveQuery {
+    since: Option<i64>,
+}
+
+/// Tokens unused for this many days count as inactive unless `since` is given
+const DEFAULT_INACTIVE_DAYS: i64 = 30;
+
+fn parse_token_id(req: &HttpRequest) -> Result<i32, UserError> {
+    req.match_info().get("id").unwrap().parse().map_err(|_| {
+        UserError::BadRequest("could not convert token id to integer")
+    })
 }
 
-pub fn get_tokens(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+fn token_details(db: &mut dyn Storage, token: Token) -> Result<api::TokenDetails, UserError> {
+    Ok(api::TokenDetails {
+        usage: db.get_token_usage(token.id)?.iter().map(|u| u.to_api()).collect(),
+        token: token.to_api(),
+    })
+}
+
+pub fn get_tokens(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
     if guard.root() {
-        let mut db = Database::new()?;
-        let tokens = db.get_tokens()?;
+        let tokens = guard.db.get_tokens()?;
         let tokens_json = serde_json::to_value(tokens).map_err(|e| {
             error!(utils::LOGGER, "{}", e);
             UserError::Internal
@@ -30,14 +51,22 @@ pub fn get_tokens(req: HttpRequest) -> Result<HttpResponse, UserError> {
 
 pub fn post_tokens(
     req: HttpRequest,
-    data: web::Json<CreateToken>,
+    pool: web::Data<Pool>,
+    data: web::Json<api::CreateToken>,
 ) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+    let mut guard = TokenGuard::new(&pool, &req)?;
     if guard.root() {
-        let mut db = Database::new()?;
-        let token = db.create_token(&data.permission, data.id)?;
-        match db.get_token(token)? {
-            Some(token) => Ok(HttpResponse::Created().json(token.json()?)),
+        let mut new_token = NewToken::from_api(&data, Utc::now().timestamp()).map_err(UserError::BadRequest)?;
+        new_token.created_by = Some(guard.token.id);
+        let secret = guard.db.create_token(&new_token)?;
+        match guard.db.get_token(secret.clone())? {
+            Some(token) => {
+                // The only response that ever contains the secret
+                Ok(HttpResponse::Created().json(api::CreatedToken {
+                    token: token.to_api(),
+                    secret,
+                }))
+            }
             None => Err(UserError::NotFound),
         }
     } else {
@@ -45,14 +74,13 @@ pub fn post_tokens(
     }
 }
 
-pub fn get_token(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+pub fn get_token(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
 
-    let mut db = Database::new()?;
     let _id = req.match_info().get("id").unwrap();
     if _id == "self" {
-        match db.get_token(utils::get_auth_token(&req)?)? {
-            Some(token) => Ok(HttpResponse::Ok().json(token.json()?)),
+        match guard.db.get_token(utils::get_auth_token(&req)?)? {
+            Some(token) => Ok(HttpResponse::Ok().json(token_details(&mut *guard.db, token)?)),
             None => Err(UserError::NotFound),
         }
     } else {
@@ -60,8 +88,8 @@ pub fn get_token(req: HttpRequest) -> Result<HttpResponse, UserError> {
             let token_id: i32 = _id.parse().map_err(|_| {
                 UserError::BadRequest("could not convert token id to integer")
             })?;
-            match db.get_token_by_id(token_id)? {
-                Some(token) => Ok(HttpResponse::Ok().json(token.json()?)),
+            match guard.db.get_token_by_id(token_id)? {
+                Some(token) => Ok(HttpResponse::Ok().json(token_details(&mut *guard.db, token)?)),
                 None => Err(UserError::NotFound),
             }
         } else {
@@ -70,17 +98,16 @@ pub fn get_token(req: HttpRequest) -> Result<HttpResponse, UserError> {
     }
 }
 
-pub fn get_token_by_userid(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+pub fn get_token_by_userid(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
 
-    let mut db = Database::new()?;
     let uid = req.match_info().get("uid").unwrap();
 
     if guard.root() {
         let uid: i64 = uid.parse().map_err(|_| {
             UserError::BadRequest("could not convert user id to integer")
         })?;
-        let tokens = db.get_token_by_userid(uid)?;
+        let tokens = guard.db.get_token_by_userid(uid)?;
         let tokens_json = serde_json::to_value(tokens).map_err(|e| {
             error!(utils::LOGGER, "{}", e);
             UserError::Internal
@@ -92,17 +119,119 @@ pub fn get_token_by_userid(req: HttpRequest) -> Result<HttpResponse, UserError>
     }
 }
 
-pub fn delete_token(req: HttpRequest) -> Result<HttpResponse, UserError> {
-    let guard = TokenGuard::new(utils::get_auth_token(&req)?)?;
+pub fn get_inactive_tokens(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    query: web::Query<InactiveQuery>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+
+    if guard.root() {
+        let since = match query.since {
+            Some(since) => NaiveDateTime::from_timestamp_opt(since, 0)
+                .ok_or(UserError::BadRequest("invalid timestamp"))?,
+            None => Utc::now().naive_utc() - Duration::days(DEFAULT_INACTIVE_DAYS),
+        };
+        let tokens = guard.db.get_inactive_tokens(since)?;
+        let tokens_json = serde_json::to_value(tokens).map_err(|e| {
+            error!(utils::LOGGER, "{}", e);
+            UserError::Internal
+        })?;
+
+        Ok(HttpResponse::Ok().json(tokens_json))
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn delete_token(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
 
     if guard.root() {
-        let mut db = Database::new()?;
         let token_id: i32 = req.match_info().get("id").unwrap().parse().map_err(|_| {
             UserError::BadRequest("could not convert token id to integer")
         })?;
-        match db.get_token_by_id(token_id)? {
+        match guard.db.get_token_by_id(token_id)? {
+            Some(_token) => {
+                guard.db.revoke_token_by_id(token_id)?;
+                Ok(HttpResponse::NoContent().body(""))
+            }
+            None => Err(UserError::NotFound),
+        }
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn get_token_limits(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+
+    if guard.root() {
+        let token = match guard.db.get_token_by_id(parse_token_id(&req)?)? {
+            Some(token) => token,
+            None => return Err(UserError::NotFound),
+        };
+        let mut overrides = guard.db.get_antiflood_overrides(token.id)?;
+        let mut limits = BTreeMap::new();
+        for name in ratelimit::LIMITS {
+            let limit = match overrides.remove(*name) {
+                Some(limit) => api::AppliedLimit { limit, overridden: true },
+                None => api::AppliedLimit {
+                    limit: ratelimit::configured_limit(name, &token.permission).clone(),
+                    overridden: false,
+                },
+            };
+            limits.insert(name.to_string(), limit);
+        }
+
+        Ok(HttpResponse::Ok().json(api::TokenLimits {
+            limits,
+            buckets: guard.db.get_antiflood_buckets(token.id)?.iter().map(|b| b.to_api()).collect(),
+        }))
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn put_token_limits(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    data: web::Json<BTreeMap<String, Limit>>,
+) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+
+    if guard.root() {
+        if data.keys().any(|name| !ratelimit::LIMITS.contains(&name.as_str())) {
+            return Err(UserError::BadRequest("unknown limit"));
+        }
+        let token_id = parse_token_id(&req)?;
+        match guard.db.get_token_by_id(token_id)? {
+            Some(_token) => {
+                guard.db.set_antiflood_overrides(token_id, &data)?;
+                if let Some(cache) = req.app_data::<OverrideCache>() {
+                    cache.invalidate(token_id);
+                }
+                Ok(HttpResponse::NoContent().body(""))
+            }
+            None => Err(UserError::NotFound),
+        }
+    } else {
+        Err(UserError::Forbidden)
+    }
+}
+
+pub fn delete_token_limits(req: HttpRequest, pool: web::Data<Pool>) -> Result<HttpResponse, UserError> {
+    let mut guard = TokenGuard::new(&pool, &req)?;
+
+    if guard.root() {
+        let token_id = parse_token_id(&req)?;
+        match guard.db.get_token_by_id(token_id)? {
             Some(_token) => {
-                db.revoke_token_by_id(token_id)?;
+                guard.db.reset_antiflood(token_id)?;
+                guard.db.set_antiflood_overrides(token_id, &BTreeMap::new())?;
+                if let Some(cache) = req.app_data::<OverrideCache>() {
+                    cache.invalidate(token_id);
+                }
                 Ok(HttpResponse::NoContent().body(""))
             }
             None => Err(UserError::NotFound),
@@ -4,18 +4,26 @@ use config::{Config, ConfigError, Environment, File};
 use dirs::home_dir;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
+use slog::{Level, Logger};
 
-use crate::utils;
+use crate::guards::Permission;
+use crate::logging;
+
+pub use spamwatch_types::Limit;
 
 lazy_static! {
-    pub static ref ENV: Settings = match Settings::load() {
-        Ok(settings) => {
-            debug!(utils::LOGGER, "Settings:"; "name" => &settings.database.name);
-            settings
-        }
-        Err(err) => {
-            error!(utils::LOGGER, "{}", &format!("{}", err));
-            Settings::default()
+    // `utils::LOGGER` is configured by these settings, so it can't be used while loading them
+    pub static ref ENV: Settings = {
+        let logger = logging::bootstrap();
+        match Settings::load(&logger) {
+            Ok(settings) => {
+                debug!(logger, "Settings:"; "name" => &settings.database.name);
+                settings
+            }
+            Err(err) => {
+                error!(logger, "{}", &format!("{}", err));
+                Settings::default()
+            }
         }
     };
 }
@@ -25,21 +33,127 @@ pub struct General {
     pub masterid: i64,
     pub token_size: u8,
     pub staging: bool,
+    pub max_check_size: usize,
+    pub expiry_interval: u64,
+    pub usage_flush_interval: u64,
+    /// Whether Admin tokens may export or page through the banlist with `GET /banlist`, which
+    /// otherwise requires Root
+    pub admin_export: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Postgres,
+    Memory,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DatabaseCfg {
+    pub backend: Backend,
+    pub migrate: bool,
     pub host: String,
     pub port: u16,
     pub name: String,
     pub username: String,
     pub password: String,
+    pub pool_size: u32,
+    pub pool_min_idle: u32,
+    pub pool_timeout: u64,
+    pub pool_idle_timeout: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServerCfg {
     pub host: String,
     pub port: u16,
+    /// Whether `/ready` also requires the Genesis Token to exist
+    pub ready_requires_genesis: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetricsCfg {
+    pub enabled: bool,
+    /// Serve `/metrics` on this address instead of the API's, e.g. `127.0.0.1:9100`
+    pub bind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Terminal,
+    Json,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl From<&LogLevel> for Level {
+    fn from(level: &LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => Level::Trace,
+            LogLevel::Debug => Level::Debug,
+            LogLevel::Info => Level::Info,
+            LogLevel::Warning => Level::Warning,
+            LogLevel::Error => Level::Error,
+            LogLevel::Critical => Level::Critical,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    Stderr,
+    File,
+    Both,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoggingCfg {
+    pub format: LogFormat,
+    /// Messages below this level are dropped
+    pub level: LogLevel,
+    pub output: LogOutput,
+    pub file: String,
+    /// Bytes after which the file is rotated. 0 disables rotation
+    pub max_size: u64,
+    /// Rotated files to keep
+    pub keep: u32,
+}
+
+/// A value that differs between the permission levels of tokens.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PerPermission<T> {
+    pub user: T,
+    pub admin: T,
+    pub root: T,
+}
+
+impl<T> PerPermission<T> {
+    pub fn get(&self, permission: &Permission) -> &T {
+        match permission {
+            Permission::User => &self.user,
+            Permission::Admin => &self.admin,
+            Permission::Root => &self.root,
+        }
+    }
+}
+
+/// The names of the fields are also used to override them for single tokens
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateLimitCfg {
+    /// Applies to every endpoint without its own limit
+    pub default: PerPermission<Limit>,
+    pub banlist_all: PerPermission<Limit>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -47,33 +161,78 @@ pub struct Settings {
     pub database: DatabaseCfg,
     pub server: ServerCfg,
     pub general: General,
+    /// Seconds a newly created token is valid for unless a expiry is given. 0 means forever
+    pub token_lifetime: PerPermission<u64>,
+    pub rate_limit: RateLimitCfg,
+    pub metrics: MetricsCfg,
+    pub logging: LoggingCfg,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
             database: DatabaseCfg {
+                backend: Backend::Postgres,
+                migrate: true,
                 host: "127.0.0.1".to_string(),
                 port: 5432,
                 name: "SpamWatchAPI".to_string(),
                 username: "SpamWatchAPI".to_string(),
                 password: String::default(),
+                pool_size: 16,
+                pool_min_idle: 2,
+                pool_timeout: 30,
+                pool_idle_timeout: 600,
             },
             server: ServerCfg {
                 host: "127.0.0.1".to_string(),
                 port: 6345,
+                ready_requires_genesis: true,
             },
             general: General {
                 masterid: 777000,
                 token_size: 64,
                 staging: false,
+                max_check_size: 1000,
+                expiry_interval: 60,
+                usage_flush_interval: 30,
+                admin_export: false,
+            },
+            token_lifetime: PerPermission {
+                user: 0,
+                admin: 0,
+                root: 0,
+            },
+            rate_limit: RateLimitCfg {
+                default: PerPermission {
+                    user: Limit { burst: 60, period: 60 },
+                    admin: Limit { burst: 600, period: 60 },
+                    root: Limit { burst: 0, period: 0 },
+                },
+                banlist_all: PerPermission {
+                    user: Limit { burst: 1, period: 300 },
+                    admin: Limit { burst: 0, period: 0 },
+                    root: Limit { burst: 0, period: 0 },
+                },
+            },
+            metrics: MetricsCfg {
+                enabled: false,
+                bind: String::default(),
+            },
+            logging: LoggingCfg {
+                format: LogFormat::Terminal,
+                level: LogLevel::Debug,
+                output: LogOutput::Stderr,
+                file: format!("{}.log", env!("CARGO_PKG_NAME")),
+                max_size: 10 * 1024 * 1024,
+                keep: 5,
             },
         }
     }
 }
 
 impl Settings {
-    pub fn load() -> Result<Self, ConfigError> {
+    pub fn load(logger: &Logger) -> Result<Self, ConfigError> {
         let home_config: PathBuf = match home_dir() {
             Some(path) => [
                 path,
@@ -82,7 +241,7 @@ impl Settings {
                 .iter()
                 .collect(),
             None => {
-                debug!(utils::LOGGER, "Can't get home directory");
+                debug!(logger, "Can't get home directory");
                 PathBuf::from("config")
             }
         };
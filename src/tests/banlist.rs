@@ -0,0 +1,596 @@
+#[cfg(test)]
+mod memory {
+    use actix_service::Service;
+    use actix_web::{App, web};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use serde_json::{json, Value};
+
+    use chrono::{Duration, Utc};
+
+    use crate::database::{MemoryDatabase, NewToken, Pool};
+    use crate::guards::Permission;
+    use crate::routes;
+    use crate::settings;
+
+    fn setup() -> (Pool, String, String) {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        db.create_genesis_token().unwrap();
+        let admin = db.create_token(&NewToken::new(Permission::Admin, 1)).unwrap();
+        let user = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        (pool, admin, user)
+    }
+
+    fn bearer(token: &str) -> String {
+        format!("Bearer {}", token)
+    }
+
+    #[test]
+    fn test_ban_roundtrip() {
+        let (pool, admin, user) = setup();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .service(
+                    web::resource("/banlist")
+                        .route(web::post().to(routes::banlist::post_bans)),
+                )
+                .service(
+                    web::resource("/banlist/{id}")
+                        .route(web::get().to(routes::banlist::get_ban))
+                        .route(web::delete().to(routes::banlist::delete_ban)),
+                ),
+        );
+        let req = test::TestRequest::post()
+            .uri("/banlist")
+            .header("Authorization", bearer(&admin))
+            .set_json(&json!([{"id": 42, "reason": "spam", "message": null}]))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/42")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let ban: Value = test::read_response_json(&mut app, req);
+        assert_eq!(ban["reason"], "spam");
+        assert_eq!(ban["admin"], 2);
+
+        let req = test::TestRequest::delete()
+            .uri("/banlist/42")
+            .header("Authorization", bearer(&admin))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/42")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_post_bans_as_user() {
+        let (pool, _admin, user) = setup();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist").route(web::post().to(routes::banlist::post_bans)),
+            ),
+        );
+        let req = test::TestRequest::post()
+            .uri("/banlist")
+            .header("Authorization", bearer(&user))
+            .set_json(&json!([{"id": 42, "reason": "spam"}]))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_get_ban_no_auth() {
+        let (pool, _admin, _user) = setup();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist/{id}").route(web::get().to(routes::banlist::get_ban)),
+            ),
+        );
+        let req = test::TestRequest::get().uri("/banlist/42").to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_banlist_all_antiflood() {
+        let (pool, _admin, user) = setup();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist/all").route(web::get().to(routes::banlist::get_bans_id_list)),
+            ),
+        );
+        let req = test::TestRequest::get()
+            .uri("/banlist/all")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/all")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn test_check_bans() {
+        let (pool, _admin, user) = setup();
+        let mut db = pool.get().unwrap();
+        db.add_ban(10, "spam", None, 2, &None, None).unwrap();
+        db.add_ban(30, "scam", None, 2, &Some("buy now".to_string()), None).unwrap();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist/check").route(web::post().to(routes::banlist::check_bans)),
+            ),
+        );
+        let req = test::TestRequest::post()
+            .uri("/banlist/check")
+            .header("Authorization", bearer(&user))
+            .set_json(&json!([10, 20, 30, 10]))
+            .to_request();
+        let bans: Value = test::read_response_json(&mut app, req);
+        let ids: Vec<i64> = bans.as_array().unwrap().iter().map(|b| b["id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, vec![10, 30]);
+
+        // An oversized request is rejected without taking from the bucket
+        let user_id = db.get_token(user.clone()).unwrap().unwrap().id;
+        let available = db.get_antiflood_buckets(user_id).unwrap()[0].available;
+        let too_many: Vec<i64> = (0..=settings::ENV.general.max_check_size as i64).collect();
+        let req = test::TestRequest::post()
+            .uri("/banlist/check")
+            .header("Authorization", bearer(&user))
+            .set_json(&too_many)
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert!(db.get_antiflood_buckets(user_id).unwrap()[0].available >= available);
+    }
+
+    #[test]
+    fn test_ban_changes() {
+        let (pool, _admin, user) = setup();
+        let mut db = pool.get().unwrap();
+        db.add_ban(10, "spam", None, 2, &None, None).unwrap();
+        db.add_ban(20, "spam", None, 2, &None, None).unwrap();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist/changes").route(web::get().to(routes::banlist::get_ban_changes)),
+            ),
+        );
+        let req = test::TestRequest::get()
+            .uri("/banlist/changes?since=0")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let changes: Value = test::read_response_json(&mut app, req);
+        assert_eq!(changes["added"], json!([10, 20]));
+        assert_eq!(changes["removed"], json!([]));
+
+        // 30 was never banned, so it wasn't unbanned either
+        db.delete_ban(10, 2).unwrap();
+        db.delete_ban(30, 2).unwrap();
+        db.add_ban(40, "spam", None, 2, &None, None).unwrap();
+        let mut get_after = |changes: &Value| -> Value {
+            let req = test::TestRequest::get()
+                .uri(&format!("/banlist/changes?cursor={}", changes["cursor"].as_str().unwrap()))
+                .header("Authorization", bearer(&user))
+                .to_request();
+            test::read_response_json(&mut app, req)
+        };
+        let changes = get_after(&changes);
+        assert_eq!((&changes["added"], &changes["removed"]), (&json!([40]), &json!([10])));
+        let changes = get_after(&changes);
+        assert_eq!((&changes["added"], &changes["removed"]), (&json!([]), &json!([])));
+
+        // Timestamps, which were used as cursors before, aren't accepted
+        for cursor in &["yesterday", "1602979200000000"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/banlist/changes?cursor={}", cursor))
+                .header("Authorization", bearer(&user))
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_ban_history() {
+        let (pool, admin, user) = setup();
+        let mut db = pool.get().unwrap();
+        db.add_ban(10, "spam", None, 2, &None, None).unwrap();
+        db.add_ban(10, "scam", None, 1, &Some("buy now".to_string()), None).unwrap();
+        db.delete_ban(10, 2).unwrap();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist/{id}/history").route(web::get().to(routes::banlist::get_ban_history)),
+            ),
+        );
+        let req = test::TestRequest::get()
+            .uri("/banlist/10/history")
+            .header("Authorization", bearer(&admin))
+            .to_request();
+        let history: Value = test::read_response_json(&mut app, req);
+        let actions: Vec<&str> = history.as_array().unwrap().iter().map(|h| h["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, vec!["Create", "Update", "Delete"]);
+        assert_eq!(history[1]["old_reason"], "spam");
+        assert_eq!(history[1]["new_reason"], "scam");
+        assert_eq!(history[1]["token"], 1);
+        assert_eq!(history[2]["old_message"], "buy now");
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/10/history")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_temporary_bans() {
+        let (pool, admin, user) = setup();
+        let mut db = pool.get().unwrap();
+        let past = Utc::now().naive_utc() - Duration::hours(1);
+        db.add_ban(10, "spam", None, 2, &None, Some(past)).unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .service(
+                    web::resource("/banlist")
+                        .route(web::post().to(routes::banlist::post_bans)),
+                )
+                .service(
+                    web::resource("/banlist/{id}")
+                        .route(web::get().to(routes::banlist::get_ban)),
+                ),
+        );
+        let req = test::TestRequest::get()
+            .uri("/banlist/10")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(db.get_total_ban_count().unwrap(), 0);
+        assert_eq!(db.archive_expired_bans().unwrap(), 1);
+        assert_eq!(db.get_ban_history(10).unwrap().len(), 2);
+
+        let expires = Utc::now().timestamp() + 3600;
+        let req = test::TestRequest::post()
+            .uri("/banlist")
+            .header("Authorization", bearer(&admin))
+            .set_json(&json!([{"id": 20, "reason": "spam", "expires": expires}]))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let req = test::TestRequest::get()
+            .uri("/banlist/20")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let ban: Value = test::read_response_json(&mut app, req);
+        assert_eq!(ban["expires"], expires);
+
+        let req = test::TestRequest::post()
+            .uri("/banlist")
+            .header("Authorization", bearer(&admin))
+            .set_json(&json!([{"id": 30, "reason": "spam", "expires": 1}]))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_export_bans() {
+        let (pool, admin, _user) = setup();
+        let mut db = pool.get().unwrap();
+        db.add_ban(2, "spam", None, 2, &Some("said \"hi\", twice".to_string()), None).unwrap();
+        db.add_ban(1, "flood", None, 2, &None, None).unwrap();
+        let root = db.create_token(&NewToken::new(Permission::Root, 3)).unwrap();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist").route(web::get().to(routes::banlist::get_bans)),
+            ),
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/banlist")
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let bans: Value = test::read_response_json(&mut app, req);
+        assert_eq!(bans[0]["id"], 1);
+        assert_eq!(bans[1]["message"], "said \"hi\", twice");
+
+        let req = test::TestRequest::get()
+            .uri("/banlist")
+            .header("Authorization", bearer(&root))
+            .header("Accept", "application/x-ndjson")
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), "application/x-ndjson");
+        let body = test::read_body(resp);
+        let lines: Vec<Value> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, bans.as_array().unwrap().clone());
+
+        let req = test::TestRequest::get()
+            .uri("/banlist?format=csv")
+            .header("Authorization", bearer(&root))
+            .header("Accept", "application/json")
+            .to_request();
+        let body = test::read_response(&mut app, req);
+        let mut lines = std::str::from_utf8(&body).unwrap().lines();
+        assert_eq!(lines.next(), Some("id,reason,date,admin,message,expires,category"));
+        assert_eq!(lines.next(), Some(format!("1,flood,{},2,,,", bans[0]["date"]).as_str()));
+        assert_eq!(lines.next(), Some(format!("2,spam,{},2,\"said \"\"hi\"\", twice\",,", bans[1]["date"]).as_str()));
+        assert_eq!(lines.next(), None);
+
+        let req = test::TestRequest::get()
+            .uri("/banlist?format=xml")
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // `admin_export` is off by default
+        let req = test::TestRequest::get()
+            .uri("/banlist")
+            .header("Authorization", bearer(&admin))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_ban_pages() {
+        let (pool, admin, user) = setup();
+        let mut db = pool.get().unwrap();
+        let root = db.create_token(&NewToken::new(Permission::Root, 3)).unwrap();
+        for (id, reason, admin_token) in &[(1, "Spam", 2), (2, "flood", 2), (3, "spam bot", 3), (4, "100%", 3), (5, "spam", 2)] {
+            let message = if *id % 2 == 0 { Some("hi".to_string()) } else { None };
+            db.add_ban(*id, reason, None, *admin_token, &message, None).unwrap();
+        }
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist").route(web::get().to(routes::banlist::get_bans)),
+            ),
+        );
+        let mut get_page = |uri: &str| -> Value {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .header("Authorization", bearer(&root))
+                .to_request();
+            test::read_response_json(&mut app, req)
+        };
+        let ids = |page: &Value| -> Vec<i64> {
+            page["bans"].as_array().unwrap().iter().map(|ban| ban["id"].as_i64().unwrap()).collect()
+        };
+
+        let page = get_page("/banlist?limit=2");
+        assert_eq!(ids(&page), vec![1, 2]);
+        assert_eq!(page["next"], "/banlist?limit=2&after=2");
+        let page = get_page(page["next"].as_str().unwrap());
+        assert_eq!(ids(&page), vec![3, 4]);
+        let page = get_page(page["next"].as_str().unwrap());
+        assert_eq!(ids(&page), vec![5]);
+        assert!(page["cursor"].is_null());
+        assert!(page["next"].is_null());
+
+        let page = get_page("/banlist?sort=date&order=desc&limit=3");
+        assert_eq!(ids(&page), vec![5, 4, 3]);
+        let page = get_page(page["next"].as_str().unwrap());
+        assert_eq!(ids(&page), vec![2, 1]);
+
+        assert_eq!(ids(&get_page("/banlist?reason=SPAM")), vec![1, 3, 5]);
+        assert_eq!(ids(&get_page("/banlist?reason=%25")), vec![4]);
+        assert_eq!(ids(&get_page("/banlist?has_message=true")), vec![2, 4]);
+        assert_eq!(ids(&get_page("/banlist?admin=3&has_message=false")), vec![3]);
+        let future = Utc::now().timestamp() + 60;
+        assert_eq!(ids(&get_page(&format!("/banlist?since={}", future))), Vec::<i64>::new());
+        assert_eq!(ids(&get_page(&format!("/banlist?until={}", future))).len(), 5);
+
+        for uri in &["/banlist?limit=0", "/banlist?limit=2&format=csv", "/banlist?after=x", "/banlist?sort=date&after=2"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .header("Authorization", bearer(&root))
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+        // Like exporting, paging requires Root while `admin_export` is off
+        for token in &[&admin, &user] {
+            let req = test::TestRequest::get()
+                .uri("/banlist?limit=2")
+                .header("Authorization", bearer(token))
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        }
+    }
+
+    #[test]
+    fn test_search_bans() {
+        let (pool, admin, user) = setup();
+        let mut db = pool.get().unwrap();
+        db.add_ban(1, "crypto scam", None, 2, &Some("Send <b>BTC</b> & get double".to_string()), None).unwrap();
+        db.add_ban(2, "spam", None, 2, &Some("crypto giveaway scam, crypto!".to_string()), None).unwrap();
+        db.add_ban(3, "flood", None, 2, &None, None).unwrap();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/banlist/search").route(web::get().to(routes::banlist::search_bans)),
+            ),
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/search?q=Crypto%20scam")
+            .header("Authorization", bearer(&admin))
+            .to_request();
+        let matches: Value = test::read_response_json(&mut app, req);
+        let ids: Vec<i64> = matches.as_array().unwrap().iter().map(|m| m["id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(matches[0]["reason_snippet"], "<mark>crypto</mark> <mark>scam</mark>");
+        assert_eq!(matches[0]["message_snippet"], "Send &lt;b&gt;BTC&lt;/b&gt; &amp; get double");
+        assert_eq!(matches[1]["message_snippet"], "<mark>crypto</mark> giveaway <mark>scam</mark>, <mark>crypto</mark>!");
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/search?q=btc&limit=1")
+            .header("Authorization", bearer(&admin))
+            .to_request();
+        let matches: Value = test::read_response_json(&mut app, req);
+        assert_eq!(matches.as_array().unwrap().len(), 1);
+        assert_eq!(matches[0]["message_snippet"], "Send &lt;b&gt;<mark>BTC</mark>&lt;/b&gt; &amp; get double");
+
+        for uri in &["/banlist/search", "/banlist/search?q=%20", "/banlist/search?q=spam&limit=101"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .header("Authorization", bearer(&admin))
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+        let req = test::TestRequest::get()
+            .uri("/banlist/search?q=spam")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn test_ban_categories() {
+        let (pool, admin, user) = setup();
+        let root = pool.get().unwrap().create_token(&NewToken::new(Permission::Root, 3)).unwrap();
+        let mut app = test::init_service(App::new().data(pool).configure(routes::configure));
+
+        let req = test::TestRequest::post()
+            .uri("/banlist/categories")
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"name": "Crypto-Scam", "description": "Fake giveaways"}))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let category: Value = serde_json::from_slice(&test::read_body(resp)).unwrap();
+        assert_eq!(category["name"], "crypto-scam");
+        let id = category["id"].as_i64().unwrap();
+
+        for (token, body, status) in &[
+            (&root, json!({"name": "CRYPTO-SCAM"}), StatusCode::CONFLICT),
+            (&root, json!({"name": "crypto scam"}), StatusCode::BAD_REQUEST),
+            (&admin, json!({"name": "spam"}), StatusCode::FORBIDDEN),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/banlist/categories")
+                .header("Authorization", bearer(token))
+                .set_json(body)
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), *status, "{}", body);
+        }
+
+        for (body, status) in &[
+            (json!([{"id": 10, "category": id}]), StatusCode::NO_CONTENT),
+            (json!([{"id": 11, "reason": "spam", "category": id + 1}]), StatusCode::BAD_REQUEST),
+            (json!([{"id": 12}]), StatusCode::BAD_REQUEST),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/banlist")
+                .header("Authorization", bearer(&admin))
+                .set_json(body)
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), *status, "{}", body);
+        }
+        let req = test::TestRequest::get()
+            .uri("/banlist/10")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let ban: Value = test::read_response_json(&mut app, req);
+        assert_eq!((ban["category"].as_i64(), ban["reason"].as_str()), (Some(id), Some("")));
+        let req = test::TestRequest::get().uri("/stats").to_request();
+        let stats: Value = test::read_response_json(&mut app, req);
+        assert_eq!(stats["categories"], json!([{"id": id, "name": "crypto-scam", "ban_count": 1}]));
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/banlist/categories/{}", id))
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"name": "scam"}))
+            .to_request();
+        let category: Value = test::read_response_json(&mut app, req);
+        assert_eq!(category, json!({"id": id, "name": "scam", "description": null}));
+        let req = test::TestRequest::get()
+            .uri("/banlist/categories")
+            .header("Authorization", bearer(&user))
+            .to_request();
+        let categories: Value = test::read_response_json(&mut app, req);
+        assert_eq!(categories, json!([category]));
+
+        let delete = || test::TestRequest::delete()
+            .uri(&format!("/banlist/categories/{}", id))
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let resp = test::block_on(app.call(delete())).unwrap();
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+        let req = test::TestRequest::delete()
+            .uri("/banlist/10")
+            .header("Authorization", bearer(&admin))
+            .to_request();
+        test::block_on(app.call(req)).unwrap();
+        let resp = test::block_on(app.call(delete())).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let resp = test::block_on(app.call(delete())).unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_bulk_bans() {
+        let (pool, admin, _user) = setup();
+        pool.get().unwrap().add_ban(10, "spam", None, 2, &None, None).unwrap();
+        let mut app = test::init_service(App::new().data(pool.clone()).configure(routes::configure));
+        let bans = json!([
+            {"id": 10, "reason": "flood"},
+            {"id": 20, "reason": "spam"},
+            {"id": 30, "reason": ""},
+            {"id": 40, "reason": "spam", "expires": 1}
+        ]);
+
+        // One invalid ban rejects the whole batch
+        let req = test::TestRequest::post()
+            .uri("/banlist")
+            .header("Authorization", bearer(&admin))
+            .set_json(&bans)
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let mut db = pool.get().unwrap();
+        assert_eq!(db.get_ban(10).unwrap().unwrap().reason, "spam");
+        assert!(db.get_ban(20).unwrap().is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/banlist?partial=true")
+            .header("Authorization", bearer(&admin))
+            .set_json(&bans)
+            .to_request();
+        let results: Value = test::read_response_json(&mut app, req);
+        assert_eq!(results, json!([
+            {"id": 10, "status": "updated"},
+            {"id": 20, "status": "created"},
+            {"id": 30, "status": "error", "error": "ban reason can not be empty"},
+            {"id": 40, "status": "error", "error": "ban expiry has to be in the future"}
+        ]));
+        assert_eq!(db.get_ban(10).unwrap().unwrap().reason, "flood");
+        assert_eq!(db.get_banned_ids().unwrap(), vec![10, 20]);
+    }
+}
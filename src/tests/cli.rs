@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod admin {
+    use crate::cli;
+    use crate::database::{migrations, MemoryDatabase, NewToken, Pool};
+    use crate::guards::Permission;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn setup() -> Pool {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        migrations::run_pending(&mut *pool.get().unwrap()).unwrap();
+        pool
+    }
+
+    #[test]
+    fn test_rotate_token() {
+        let pool = setup();
+        let mut db = pool.get().unwrap();
+        let old = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        let id = db.get_token(old.clone()).unwrap().unwrap().id;
+
+        let new = db.rotate_token(id).unwrap();
+        assert_ne!(old, new);
+        assert!(db.get_token(old).unwrap().is_none());
+        assert_eq!(db.get_token(new).unwrap().unwrap().id, id);
+    }
+
+    #[test]
+    fn test_tokens() {
+        let pool = setup();
+        let mut db = pool.get().unwrap();
+        assert_eq!(cli::run(&pool, &args(&["tokens", "create", "admin", "2", "--name", "Bot"])).unwrap(), 0);
+        let token = db.get_token_by_userid(2).unwrap().remove(0);
+        assert_eq!(token.permission, Permission::Admin);
+        assert_eq!(token.name.as_deref(), Some("Bot"));
+
+        let id = token.id.to_string();
+        assert_eq!(cli::run(&pool, &args(&["tokens", "revoke", &id])).unwrap(), 0);
+        assert!(db.get_token_by_id(token.id).unwrap().unwrap().retired);
+        assert_eq!(cli::run(&pool, &args(&["tokens", "rotate", &id])).unwrap(), 1);
+        assert_eq!(cli::run(&pool, &args(&["tokens", "revoke", "999"])).unwrap(), 1);
+
+        assert_eq!(cli::run(&pool, &args(&["tokens", "create", "owner", "2"])).unwrap(), 2);
+        assert_eq!(cli::run(&pool, &args(&["tokens", "create", "user", "2", "--expires", "0"])).unwrap(), 2);
+        assert_eq!(cli::run(&pool, &args(&["tokens", "create", "user", "2", "--name"])).unwrap(), 2);
+        let long_name = "x".repeat(65);
+        assert_eq!(cli::run(&pool, &args(&["tokens", "create", "user", "3", "--name", &long_name])).unwrap(), 2);
+        assert!(db.get_token_by_userid(3).unwrap().is_empty());
+        assert_eq!(cli::run(&pool, &args(&["tokens", "list", "--owner", "x"])).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_reissue_genesis() {
+        let pool = setup();
+        let mut db = pool.get().unwrap();
+        assert_eq!(cli::run(&pool, &args(&["tokens", "reissue-genesis"])).unwrap(), 0);
+        let genesis = db.get_token_by_id(1).unwrap().unwrap();
+        assert_eq!(genesis.permission, Permission::Root);
+
+        assert_eq!(cli::run(&pool, &args(&["tokens", "reissue-genesis"])).unwrap(), 0);
+        assert_eq!(db.get_tokens().unwrap().len(), 1);
+
+        db.revoke_token_by_id(1).unwrap();
+        assert_eq!(cli::run(&pool, &args(&["tokens", "reissue-genesis"])).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_bans() {
+        let pool = setup();
+        let mut db = pool.get().unwrap();
+        assert_eq!(cli::run(&pool, &args(&["bans", "get", "777"])).unwrap(), 1);
+        assert_eq!(cli::run(&pool, &args(&["bans", "add", "777", "spam", "--message", "hi"])).unwrap(), 0);
+        let ban = db.get_ban(777).unwrap().unwrap();
+        assert_eq!(ban.reason, "spam");
+        assert_eq!(ban.admin, 1);
+        assert_eq!(ban.message.as_deref(), Some("hi"));
+        assert_eq!(cli::run(&pool, &args(&["bans", "get", "777"])).unwrap(), 0);
+
+        assert_eq!(cli::run(&pool, &args(&["bans", "remove", "777"])).unwrap(), 0);
+        assert!(db.get_ban(777).unwrap().is_none());
+        assert_eq!(cli::run(&pool, &args(&["bans", "remove", "777"])).unwrap(), 1);
+        assert_eq!(cli::run(&pool, &args(&["bans", "add", "abc", "spam"])).unwrap(), 2);
+
+        // Rejected like they are by the API
+        assert_eq!(cli::run(&pool, &args(&["bans", "add", "778", ""])).unwrap(), 2);
+        assert_eq!(cli::run(&pool, &args(&["bans", "add", "778", "", "--category", "1"])).unwrap(), 2);
+        assert_eq!(cli::run(&pool, &args(&["bans", "add", "778", "spam", "--expires", "0"])).unwrap(), 2);
+        assert!(db.get_ban(778).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_schema_out_of_date() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        for command in &[&["stats"][..], &["tokens", "list"], &["bans", "add", "777", "spam"]] {
+            assert_eq!(cli::run(&pool, &args(command)).unwrap(), 1);
+        }
+        assert!(pool.get().unwrap().get_ban(777).unwrap().is_none());
+
+        assert_eq!(cli::run(&pool, &args(&["migrations"])).unwrap(), 0);
+        assert_eq!(cli::run(&pool, &args(&["migrate"])).unwrap(), 0);
+        assert_eq!(cli::run(&pool, &args(&["stats"])).unwrap(), 0);
+    }
+}
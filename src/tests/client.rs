@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod server {
+    use std::collections::BTreeMap;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use actix_rt::{System, SystemRunner};
+    use actix_web::{App, HttpServer};
+    use spamwatch_client::{BanListQuery, BanStatus, Client, ClientError, CreateBan, CreateBanCategory, CreateToken,
+                           Limit, Permission};
+
+    use crate::database::{MemoryDatabase, NewToken, Pool};
+    use crate::requestid::RequestLog;
+    use crate::routes;
+
+    /// Runs the API on a free port and returns its URL.
+    fn serve(pool: Pool) -> String {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new("test-server");
+            let server = HttpServer::new(move || {
+                App::new().data(pool.clone()).wrap(RequestLog).configure(routes::configure)
+            })
+                .bind("127.0.0.1:0")
+                .unwrap();
+            tx.send(server.addrs()[0]).unwrap();
+            server.start();
+            system.run()
+        });
+        format!("http://{}", rx.recv().unwrap())
+    }
+
+    fn setup() -> (SystemRunner, String, String) {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let root = pool.get().unwrap().create_token(&NewToken::new(Permission::Root, 1)).unwrap();
+        let url = serve(pool);
+        // The client and the server need an actix system on their own threads
+        (System::new("test-client"), url, root)
+    }
+
+    #[test]
+    fn test_client_roundtrip() {
+        let (mut system, url, root) = setup();
+        let root = Client::new(url.clone(), root);
+
+        let version = system.block_on(root.version()).unwrap();
+        assert_eq!(version.version, env!("CARGO_PKG_VERSION"));
+
+        let mut new_token = CreateToken::new(5, Permission::Admin);
+        new_token.name = Some("Bot".to_string());
+        let created = system.block_on(root.create_token(&new_token)).unwrap();
+        assert_eq!(created.token.name, Some("Bot".to_string()));
+        let admin = Client::new(url, created.secret);
+
+        let category = CreateBanCategory { name: "Spam".to_string(), description: None };
+        let category = system.block_on(root.create_ban_category(&category)).unwrap();
+        assert_eq!(system.block_on(admin.ban_categories()).unwrap(), vec![category.clone()]);
+
+        let mut ban = CreateBan::new(10, "spam");
+        ban.message = Some("buy now".to_string());
+        ban.category = Some(category.id);
+        system.block_on(admin.add_bans(&[ban])).unwrap();
+        let ban = system.block_on(admin.ban(10)).unwrap().unwrap();
+        assert_eq!((ban.reason.as_str(), ban.admin, ban.category), ("spam", created.token.id, Some(category.id)));
+        assert_eq!(system.block_on(admin.ban(11)).unwrap(), None);
+        let results = system.block_on(admin.add_bans_partial(&[CreateBan::new(11, "spam"), CreateBan::new(12, "")]))
+            .unwrap();
+        assert_eq!((results[0].status, results[1].status), (BanStatus::Created, BanStatus::Error));
+        system.block_on(admin.delete_ban(11)).unwrap();
+        assert_eq!(system.block_on(admin.banned_ids()).unwrap(), vec![10]);
+        assert_eq!(system.block_on(admin.check_bans(&[10, 11])).unwrap(), vec![ban.clone()]);
+        assert_eq!(system.block_on(admin.ban_changes_since(0)).unwrap().added, vec![10]);
+        assert_eq!(system.block_on(root.bans()).unwrap(), vec![ban.clone()]);
+        let query = BanListQuery { reason: Some("SPAM".to_string()), has_message: Some(true), ..Default::default() };
+        let page = system.block_on(root.ban_page(&query)).unwrap();
+        assert_eq!((page.bans, page.cursor), (vec![ban.clone()], None));
+        let found = system.block_on(admin.search_bans("buy & now", Some(5))).unwrap();
+        assert_eq!(found[0].ban, ban);
+        assert_eq!(found[0].message_snippet.as_deref(), Some("<mark>buy</mark> <mark>now</mark>"));
+
+        system.block_on(admin.delete_ban(10)).unwrap();
+        assert_eq!(system.block_on(admin.ban(10)).unwrap(), None);
+        assert_eq!(system.block_on(admin.ban_history(10)).unwrap().len(), 2);
+        system.block_on(root.delete_ban_category(category.id)).unwrap();
+
+        match system.block_on(admin.tokens()) {
+            Err(ClientError::Api(e)) => {
+                assert_eq!(e.code, 403);
+                assert!(e.request_id.is_some());
+            }
+            other => panic!("expected 403, got {:?}", other.map(|_| ())),
+        }
+        let own = system.block_on(admin.own_token()).unwrap();
+        assert_eq!(own.token, created.token);
+        system.block_on(root.revoke_token(created.token.id)).unwrap();
+        assert_eq!(system.block_on(admin.ban(10)).unwrap_err().status(), Some(401));
+    }
+
+    #[test]
+    fn test_client_rate_limit() {
+        let (mut system, url, root) = setup();
+        let root = Client::new(url.clone(), root);
+        let created = system.block_on(root.create_token(&CreateToken::new(5, Permission::User))).unwrap();
+        let mut limits = BTreeMap::new();
+        limits.insert("default".to_string(), Limit { burst: 1, period: 1 });
+        system.block_on(root.set_token_limits(created.token.id, &limits)).unwrap();
+        let mut user = Client::new(url, created.secret);
+
+        // The second request has to wait for the bucket to refill
+        let start = Instant::now();
+        system.block_on(user.stats()).unwrap();
+        system.block_on(user.ban(10)).unwrap();
+        system.block_on(user.ban(10)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(500));
+
+        user.max_retries = 0;
+        match system.block_on(user.ban(10)) {
+            Err(ClientError::RateLimited { .. }) => {}
+            other => panic!("expected to be rate limited, got {:?}", other),
+        }
+        let limits = system.block_on(root.token_limits(created.token.id)).unwrap();
+        assert!(limits.limits["default"].overridden);
+    }
+}
@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod rotation {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    use crate::logging::RotatingFile;
+    use crate::settings::{LogFormat, LoggingCfg, LogLevel, LogOutput};
+
+    #[test]
+    fn test_rotating_file() {
+        let dir = env::temp_dir().join(format!("{}-rotation-{}", env!("CARGO_PKG_NAME"), std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("api.log");
+        let cfg = LoggingCfg {
+            format: LogFormat::Json,
+            level: LogLevel::Debug,
+            output: LogOutput::File,
+            file: path.to_str().unwrap().to_string(),
+            max_size: 10,
+            keep: 2,
+        };
+        let mut file = RotatingFile::open(&cfg).unwrap();
+        for line in &["first", "second", "third", "fourth"] {
+            // Lines are written in pieces, which mustn't end up in different files
+            file.write_all(line.as_bytes()).unwrap();
+            file.write_all(b" line\n").unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(fs::read_to_string(dir.join("api.log.1")).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(dir.join("api.log.2")).unwrap(), "second line\n");
+        assert!(!dir.join("api.log.3").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod request_id {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use actix_service::Service;
+    use actix_web::{App, HttpResponse, web};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use serde_json::Value;
+    use slog::{Drain, Logger};
+
+    use crate::database::{MemoryDatabase, Pool};
+    use crate::logging::RequestIdDrain;
+    use crate::requestid::{self, RequestLog};
+    use crate::routes;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn logged_ids(app_log: impl Fn(&Logger)) -> Vec<Value> {
+        let buffer = Buffer::default();
+        let drain = slog_json::Json::new(buffer.clone()).build().fuse();
+        let logger = Logger::root(Mutex::new(RequestIdDrain(drain)).fuse(), o!());
+        app_log(&logger);
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output.lines().map(|line| serde_json::from_str::<Value>(line).unwrap()["request_id"].clone()).collect()
+    }
+
+    #[test]
+    fn test_request_id() {
+        let mut app = test::init_service(
+            App::new()
+                .data(Pool::Memory(MemoryDatabase::default()))
+                .wrap(RequestLog)
+                .route("/current", web::get().to(|| {
+                    HttpResponse::Ok().body(requestid::current().unwrap_or_default())
+                }))
+                .route("/tokens", web::get().to(routes::tokens::get_tokens)),
+        );
+        let req = test::TestRequest::get().uri("/current").to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        let id = resp.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+        assert!(!id.is_empty());
+        assert_eq!(test::read_body(resp), id.as_bytes());
+        assert_eq!(requestid::current(), None);
+
+        let req = test::TestRequest::get()
+            .uri("/tokens")
+            .header("X-Request-Id", "client-42")
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers().get("x-request-id").unwrap(), "client-42");
+        let error: Value = serde_json::from_slice(&test::read_body(resp)).unwrap();
+        assert_eq!(error["request_id"], "client-42");
+
+        // IDs that could mess up the log are replaced
+        let req = test::TestRequest::get()
+            .uri("/current")
+            .header("X-Request-Id", "two words")
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_ne!(resp.headers().get("x-request-id").unwrap(), "two words");
+    }
+
+    #[test]
+    fn test_request_id_drain() {
+        let ids = logged_ids(|logger| {
+            info!(logger, "outside");
+            let mut app = test::init_service(
+                App::new().wrap(RequestLog).route("/", web::get().to({
+                    let logger = logger.clone();
+                    move || {
+                        info!(logger, "inside");
+                        HttpResponse::Ok().finish()
+                    }
+                })),
+            );
+            let req = test::TestRequest::get().uri("/").header("X-Request-Id", "abc").to_request();
+            test::block_on(app.call(req)).unwrap();
+        });
+        assert_eq!(ids, vec![Value::Null, Value::from("abc")]);
+    }
+}
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod embedded {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::database::{MemoryDatabase, Pool};
+    use crate::database::migrations::{self, MIGRATIONS};
+
+    #[test]
+    fn test_all_migrations_embedded() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+        let mut on_disk: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        on_disk.sort();
+        let embedded: Vec<String> = MIGRATIONS
+            .iter()
+            .map(|m| format!("{}_{}", m.version, m.name))
+            .collect();
+        assert_eq!(on_disk, embedded);
+    }
+
+    #[test]
+    fn test_apply_and_rollback() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        assert_eq!(migrations::run_pending(&mut *db).unwrap(), MIGRATIONS.len());
+        assert!(migrations::pending(&mut *db).unwrap().is_empty());
+
+        assert_eq!(migrations::rollback(&mut *db, 1).unwrap(), 1);
+        let pending = migrations::pending(&mut *db).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].version, migrations::latest_version());
+    }
+}
@@ -1,2 +1,9 @@
+mod banlist;
+mod cli;
+mod client;
+mod logging;
+mod migrations;
+mod openapi;
+mod ratelimit;
 mod root;
 mod tokens;
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod spec {
+    use std::collections::BTreeSet;
+
+    use actix_web::{App, test};
+    use chrono::Utc;
+    use serde_json::{json, Value};
+    use spamwatch_types as api;
+
+    use crate::database::{Antiflood, MemoryDatabase, Pool, TokenUsage};
+    use crate::routes;
+    use crate::routes::openapi;
+
+    fn keys(value: &Value) -> BTreeSet<String> {
+        value.as_object().unwrap().keys().cloned().collect()
+    }
+
+    fn properties(spec: &Value, schema: &str) -> BTreeSet<String> {
+        keys(&spec["components"]["schemas"][schema]["properties"])
+    }
+
+    fn references(value: &Value, found: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get("$ref") {
+                    found.push(reference.clone());
+                }
+                map.values().for_each(|v| references(v, found));
+            }
+            Value::Array(values) => values.iter().for_each(|v| references(v, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_every_endpoint_documented() {
+        let spec = openapi::spec();
+        let undocumented: Vec<String> = routes::endpoints()
+            .iter()
+            .filter(|e| !spec["paths"][e.path][e.method.as_str().to_lowercase()].is_object())
+            .map(|e| format!("{} {}", e.method, e.path))
+            .collect();
+        assert!(undocumented.is_empty(), "undocumented endpoints: {:?}", undocumented);
+
+        let registered: BTreeSet<String> = routes::endpoints()
+            .iter()
+            .map(|e| format!("{} {}", e.method.as_str().to_lowercase(), e.path))
+            .collect();
+        for (path, operations) in spec["paths"].as_object().unwrap() {
+            for method in operations.as_object().unwrap().keys() {
+                assert!(registered.contains(&format!("{} {}", method, path)), "{} {} isn't registered", method, path);
+            }
+        }
+
+        let mut found = Vec::new();
+        references(&spec, &mut found);
+        for reference in found {
+            let pointer = reference.trim_start_matches('#');
+            assert!(spec.pointer(pointer).is_some(), "dangling reference {}", reference);
+        }
+    }
+
+    #[test]
+    fn test_schemas_match_types() {
+        let spec = openapi::spec();
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        db.create_genesis_token().unwrap();
+        db.add_ban(10, "spam", None, 1, &None, None).unwrap();
+        let now = Utc::now().naive_utc();
+
+        let token = db.get_token_by_id(1).unwrap().unwrap();
+        assert_eq!(keys(&json!(token.to_api())), properties(&spec, "Token"));
+        let ban = db.get_ban(10).unwrap().unwrap();
+        assert_eq!(keys(&ban.raw_json()), properties(&spec, "Ban"));
+        let category = db.create_ban_category("spam", &None).unwrap();
+        assert_eq!(keys(&json!(category.to_api())), properties(&spec, "BanCategory"));
+        let stats = api::Stats {
+            total_ban_count: 1,
+            categories: vec![api::CategoryStats { id: category.id, name: category.name, ban_count: 0 }],
+        };
+        assert_eq!(keys(&json!(stats)), properties(&spec, "Stats"));
+        assert_eq!(keys(&json!(stats.categories[0])), properties(&spec, "CategoryStats"));
+        let page = api::BanPage { bans: vec![ban.to_api()], cursor: None, next: None };
+        assert_eq!(keys(&json!(page)), properties(&spec, "BanPage"));
+        let found = &db.search_bans("spam", 1).unwrap()[0];
+        assert_eq!(keys(&json!(found.to_api())), properties(&spec, "BanMatch"));
+        let result = api::BanResult { id: 10, status: api::BanStatus::Error, error: Some("invalid".to_string()) };
+        assert_eq!(keys(&json!(result)), properties(&spec, "BanResult"));
+        let history = &db.get_ban_history(10).unwrap()[0];
+        assert_eq!(keys(&history.raw_json()), properties(&spec, "BanHistory"));
+        let usage = TokenUsage { token_id: 1, endpoint: "GET /".to_string(), requests: 1, last_used_at: now };
+        assert_eq!(keys(&json!(usage.to_api())), properties(&spec, "TokenUsage"));
+        let bucket = Antiflood { endpoint: "GET /".to_string(), available: 1.0, updated_at: now };
+        assert_eq!(keys(&json!(bucket.to_api())), properties(&spec, "Bucket"));
+
+    }
+
+    #[test]
+    fn test_openapi_endpoint() {
+        let mut app = test::init_service(
+            App::new()
+                .data(Pool::Memory(MemoryDatabase::default()))
+                .configure(routes::configure),
+        );
+        let req = test::TestRequest::get().uri("/openapi.json").to_request();
+        let spec: Value = test::read_response_json(&mut app, req);
+        assert_eq!(spec["openapi"], "3.0.3");
+        assert!(spec["paths"]["/banlist/{id}"]["get"].is_object());
+    }
+}
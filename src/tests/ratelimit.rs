@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod bucket {
+    use chrono::{Duration, NaiveDateTime};
+
+    use crate::ratelimit;
+    use crate::settings::Limit;
+
+    #[test]
+    fn test_take_and_refill() {
+        let limit = Limit { burst: 2, period: 4 };
+        let start = NaiveDateTime::from_timestamp(1_600_000_000, 0);
+        let mut bucket = ratelimit::new_bucket("GET /", &limit, start);
+
+        let first = ratelimit::take(&mut bucket, &limit, start);
+        assert_eq!((first.remaining, first.retry_at), (1, None));
+        let second = ratelimit::take(&mut bucket, &limit, start);
+        assert_eq!((second.remaining, second.retry_at), (0, None));
+        assert_eq!(second.reset, start.timestamp() + 4);
+
+        let rejected = ratelimit::take(&mut bucket, &limit, start + Duration::seconds(1));
+        assert_eq!(rejected.retry_at, Some(start.timestamp() + 2));
+
+        let refilled = ratelimit::take(&mut bucket, &limit, start + Duration::seconds(2));
+        assert_eq!((refilled.remaining, refilled.retry_at), (0, None));
+    }
+}
+
+#[cfg(test)]
+mod memory {
+    use actix_service::Service;
+    use actix_web::{App, web};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use serde_json::{json, Value};
+
+    use crate::database::{MemoryDatabase, NewToken, Pool};
+    use crate::guards::Permission;
+    use crate::ratelimit::{OverrideCache, RateLimitHeaders};
+    use crate::routes;
+    use crate::settings;
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        let user = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        let mut app = test::init_service(
+            App::new().data(pool).wrap(RateLimitHeaders).service(
+                web::resource("/banlist/{id}").route(web::get().to(routes::banlist::get_ban)),
+            ),
+        );
+        let burst = settings::ENV.rate_limit.default.user.burst;
+        for remaining in (0..burst).rev() {
+            let req = test::TestRequest::get()
+                .uri(&format!("/banlist/{}", remaining))
+                .header("Authorization", format!("Bearer {}", user))
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            assert_eq!(resp.headers().get("x-ratelimit-limit").unwrap(), burst.to_string().as_str());
+            assert_eq!(resp.headers().get("x-ratelimit-remaining").unwrap(), remaining.to_string().as_str());
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/banlist/42")
+            .header("Authorization", format!("Bearer {}", user))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get("x-ratelimit-remaining").unwrap(), "0");
+        assert!(resp.headers().contains_key("retry-after"));
+    }
+
+    #[test]
+    fn test_limit_overrides() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        let root = db.create_token(&NewToken::new(Permission::Root, 1)).unwrap();
+        let user = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        // Overrides of the user are cached by its first request, PUT has to invalidate them
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .data(OverrideCache::default())
+                .wrap(RateLimitHeaders)
+                .service(
+                    web::resource("/banlist/all").route(web::get().to(routes::banlist::get_bans_id_list)),
+                )
+                .service(
+                    web::resource("/tokens/{id}/limits")
+                        .route(web::get().to(routes::tokens::get_token_limits))
+                        .route(web::put().to(routes::tokens::put_token_limits))
+                        .route(web::delete().to(routes::tokens::delete_token_limits)),
+                ),
+        );
+        let banlist_all = |token: &str| test::TestRequest::get()
+            .uri("/banlist/all")
+            .header("Authorization", format!("Bearer {}", token))
+            .to_request();
+        for _ in 0..settings::ENV.rate_limit.banlist_all.user.burst {
+            test::block_on(app.call(banlist_all(&user))).unwrap();
+        }
+        let resp = test::block_on(app.call(banlist_all(&user))).unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let req = test::TestRequest::put()
+            .uri("/tokens/2/limits")
+            .header("Authorization", format!("Bearer {}", root))
+            .set_json(&json!({"banlist_all": {"burst": 5, "period": 60}}))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let resp = test::block_on(app.call(banlist_all(&user))).unwrap();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get("x-ratelimit-limit").unwrap(), "5");
+
+        let req = test::TestRequest::get()
+            .uri("/tokens/2/limits")
+            .header("Authorization", format!("Bearer {}", root))
+            .to_request();
+        let limits: Value = test::read_response_json(&mut app, req);
+        assert_eq!(limits["limits"]["banlist_all"], json!({"burst": 5, "period": 60, "overridden": true}));
+        assert_eq!(limits["limits"]["default"]["overridden"], false);
+        assert_eq!(limits["buckets"][0]["endpoint"], "GET /banlist/all");
+
+        let req = test::TestRequest::put()
+            .uri("/tokens/2/limits")
+            .header("Authorization", format!("Bearer {}", root))
+            .set_json(&json!({"everything": {"burst": 5, "period": 60}}))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_reset_limits() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        let root = db.create_token(&NewToken::new(Permission::Root, 1)).unwrap();
+        let user = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .data(OverrideCache::default())
+                .wrap(RateLimitHeaders)
+                .service(
+                    web::resource("/banlist/all").route(web::get().to(routes::banlist::get_bans_id_list)),
+                )
+                .service(
+                    web::resource("/tokens/{id}/limits")
+                        .route(web::get().to(routes::tokens::get_token_limits))
+                        .route(web::put().to(routes::tokens::put_token_limits))
+                        .route(web::delete().to(routes::tokens::delete_token_limits)),
+                ),
+        );
+        let banlist_all = |token: &str| test::TestRequest::get()
+            .uri("/banlist/all")
+            .header("Authorization", format!("Bearer {}", token))
+            .to_request();
+        let req = test::TestRequest::put()
+            .uri("/tokens/2/limits")
+            .header("Authorization", format!("Bearer {}", root))
+            .set_json(&json!({"banlist_all": {"burst": 1, "period": 60}}))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        // Caches the override before it is removed
+        let resp = test::block_on(app.call(banlist_all(&user))).unwrap();
+        assert_eq!(resp.headers().get("x-ratelimit-limit").unwrap(), "1");
+
+        let req = test::TestRequest::delete()
+            .uri("/tokens/2/limits")
+            .header("Authorization", format!("Bearer {}", root))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let burst = settings::ENV.rate_limit.banlist_all.user.burst;
+        let resp = test::block_on(app.call(banlist_all(&user))).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("x-ratelimit-limit").unwrap(), burst.to_string().as_str());
+
+        let req = test::TestRequest::get()
+            .uri("/tokens/2/limits")
+            .header("Authorization", format!("Bearer {}", root))
+            .to_request();
+        let limits: Value = test::read_response_json(&mut app, req);
+        assert_eq!(limits["limits"]["banlist_all"]["overridden"], false);
+        assert_eq!(limits["buckets"][0]["available"], f64::from(burst - 1));
+    }
+
+    #[test]
+    fn test_encoded_paths_share_bucket() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        let user = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        let mut app = test::init_service(App::new().data(pool.clone()).configure(routes::configure));
+        let get = |uri: &str| test::TestRequest::get()
+            .uri(uri)
+            .header("Authorization", format!("Bearer {}", user))
+            .to_request();
+        for _ in 0..settings::ENV.rate_limit.banlist_all.user.burst {
+            test::block_on(app.call(get("/banlist/all"))).unwrap();
+        }
+        for uri in &["/banlist/%61ll", "/banlist/a%6cl"] {
+            let resp = test::block_on(app.call(get(uri))).unwrap();
+            assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        test::block_on(app.call(get("/banlist/%31"))).unwrap();
+        let mut db = pool.get().unwrap();
+        let buckets: Vec<String> = db.get_antiflood_buckets(1).unwrap().into_iter().map(|b| b.endpoint).collect();
+        assert_eq!(buckets, vec!["GET /banlist/all", "GET /banlist/{id}"]);
+    }
+}
@@ -147,3 +147,72 @@ mod delete {
         assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
     }
 }
+
+#[cfg(test)]
+mod memory {
+    use actix_service::Service;
+    use actix_web::{App, web};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    use serde_json::{json, Value};
+
+    use crate::database::{MemoryDatabase, Pool};
+    use crate::database::migrations::{self, MIGRATIONS};
+    use crate::metrics::Metrics;
+    use crate::routes;
+
+    #[test]
+    fn test_metrics() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .wrap(Metrics)
+                .service(web::resource("/metrics").route(web::get().to(routes::root::metrics)))
+                .service(web::resource("/banlist/{id}").route(web::get().to(routes::banlist::get_ban))),
+        );
+        let req = test::TestRequest::get().uri("/banlist/42").to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let body = String::from_utf8(test::read_response(&mut app, req).to_vec()).unwrap();
+        assert!(body.contains(r#"spamwatch_http_requests_total{method="GET",route="/banlist/{id}",status="401"}"#));
+        assert!(body.contains(r#"spamwatch_errors_total{error="Unauthorized"}"#));
+        assert!(body.contains("spamwatch_bans 0"));
+        assert!(body.contains(r#"spamwatch_database_duration_seconds_count{method="get_total_ban_count"}"#));
+    }
+
+    #[test]
+    fn test_health_and_ready() {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .service(web::resource("/health").route(web::get().to(routes::root::health)))
+                .service(web::resource("/ready").route(web::get().to(routes::root::ready))),
+        );
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = serde_json::from_slice(&test::read_body(resp)).unwrap();
+        assert_eq!(body["checks"]["database"], "ok");
+        assert_eq!(body["checks"]["schema"], format!("{} migration(s) pending", MIGRATIONS.len()));
+        assert_eq!(body["checks"]["genesis_token"], "missing");
+
+        migrations::run_pending(&mut *db).unwrap();
+        db.create_genesis_token().unwrap();
+        let req = test::TestRequest::get().uri("/ready").to_request();
+        let body: Value = test::read_response_json(&mut app, req);
+        assert_eq!(body, json!({
+            "status": "ready",
+            "checks": {"database": "ok", "schema": "ok", "genesis_token": "ok"}
+        }));
+    }
+}
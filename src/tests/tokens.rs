@@ -167,3 +167,214 @@ mod delete {
         assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
     }
 }
+
+#[cfg(test)]
+mod memory {
+    use actix_service::Service;
+    use actix_web::{App, web};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use chrono::{Duration, Utc};
+    use serde_json::{json, Value};
+
+    use crate::database::{MemoryDatabase, NewToken, Pool};
+    use crate::guards::Permission;
+    use crate::routes;
+    use crate::usage::UsageTracker;
+    use crate::utils;
+
+    fn setup() -> (Pool, String) {
+        let pool = Pool::Memory(MemoryDatabase::default());
+        let mut db = pool.get().unwrap();
+        let root = db.create_token(&NewToken::new(Permission::Root, 1)).unwrap();
+        (pool, root)
+    }
+
+    fn bearer(token: &str) -> String {
+        format!("Bearer {}", token)
+    }
+
+    #[test]
+    fn test_secret_only_shown_once() {
+        let (pool, root) = setup();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .service(
+                    web::resource("/tokens")
+                        .route(web::get().to(routes::tokens::get_tokens))
+                        .route(web::post().to(routes::tokens::post_tokens)),
+                )
+                .service(
+                    web::resource("/tokens/{id}")
+                        .route(web::get().to(routes::tokens::get_token)),
+                ),
+        );
+        let req = test::TestRequest::post()
+            .uri("/tokens")
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"id": 2, "permission": "User"}))
+            .to_request();
+        let created: Value = test::read_response_json(&mut app, req);
+        let secret = created["token"].as_str().unwrap().to_string();
+        assert_eq!(created["id"], 2);
+
+        let req = test::TestRequest::get()
+            .uri("/tokens")
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let tokens: Value = test::read_response_json(&mut app, req);
+        assert_eq!(tokens.as_array().unwrap().len(), 2);
+        assert!(tokens.as_array().unwrap().iter().all(|t| t.get("token").is_none()));
+
+        let req = test::TestRequest::get()
+            .uri("/tokens/self")
+            .header("Authorization", bearer(&secret))
+            .to_request();
+        let token: Value = test::read_response_json(&mut app, req);
+        assert_eq!(token["id"], 2);
+        assert!(token.get("token").is_none());
+
+        let req = test::TestRequest::get()
+            .uri("/tokens/self")
+            .header("Authorization", bearer(&utils::hash_token(&secret)))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let (pool, root) = setup();
+        let mut db = pool.get().unwrap();
+        let mut new_token = NewToken::new(Permission::User, 2);
+        new_token.expires_at = Some(Utc::now().naive_utc() - Duration::minutes(1));
+        let expired = db.create_token(&new_token).unwrap();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .service(
+                    web::resource("/tokens")
+                        .route(web::post().to(routes::tokens::post_tokens)),
+                )
+                .service(
+                    web::resource("/tokens/{id}")
+                        .route(web::get().to(routes::tokens::get_token)),
+                ),
+        );
+        let req = test::TestRequest::get()
+            .uri("/tokens/self")
+            .header("Authorization", bearer(&expired))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = serde_json::from_slice(&test::read_body(resp)).unwrap();
+        assert_eq!(body["reason"], "token expired");
+
+        let expires_at = Utc::now().timestamp() + 3600;
+        let req = test::TestRequest::post()
+            .uri("/tokens")
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"id": 3, "permission": "Admin", "expires_at": expires_at}))
+            .to_request();
+        let created: Value = test::read_response_json(&mut app, req);
+        assert_eq!(created["expires_at"], expires_at);
+
+        let req = test::TestRequest::post()
+            .uri("/tokens")
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"id": 3, "permission": "Admin", "expires_at": 1}))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_token_metadata() {
+        let (pool, root) = setup();
+        let mut app = test::init_service(
+            App::new().data(pool).service(
+                web::resource("/tokens")
+                    .route(web::post().to(routes::tokens::post_tokens)),
+            ),
+        );
+        let req = test::TestRequest::post()
+            .uri("/tokens")
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"id": 2, "permission": "User", "name": "bot", "description": "Moderation bot"}))
+            .to_request();
+        let created: Value = test::read_response_json(&mut app, req);
+        assert_eq!(created["name"], "bot");
+        assert_eq!(created["description"], "Moderation bot");
+        assert_eq!(created["created_by"], 1);
+        assert!(created["created_at"].as_i64().unwrap() <= Utc::now().timestamp());
+
+        let req = test::TestRequest::post()
+            .uri("/tokens")
+            .header("Authorization", bearer(&root))
+            .set_json(&json!({"id": 2, "permission": "User", "name": "x".repeat(65)}))
+            .to_request();
+        let resp = test::block_on(app.call(req)).unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_token_usage() {
+        let (pool, root) = setup();
+        let mut db = pool.get().unwrap();
+        let user = db.create_token(&NewToken::new(Permission::User, 2)).unwrap();
+        db.create_token(&NewToken::new(Permission::User, 3)).unwrap();
+        let usage = UsageTracker::default();
+        let mut app = test::init_service(
+            App::new()
+                .data(pool)
+                .data(usage.clone())
+                .service(
+                    web::resource("/tokens/inactive")
+                        .route(web::get().to(routes::tokens::get_inactive_tokens)),
+                )
+                .service(
+                    web::resource("/tokens/{id}")
+                        .route(web::get().to(routes::tokens::get_token)),
+                ),
+        );
+        for _ in 0..2 {
+            let req = test::TestRequest::get()
+                .uri("/tokens/self")
+                .header("Authorization", bearer(&user))
+                .to_request();
+            let resp = test::block_on(app.call(req)).unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        assert_eq!(usage.flush(&mut *db).unwrap(), 1);
+
+        let req = test::TestRequest::get()
+            .uri("/tokens/2")
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let token: Value = test::read_response_json(&mut app, req);
+        assert!(token["last_used_at"].is_i64());
+        assert_eq!(token["usage"], json!([{
+            "endpoint": "GET /tokens/{id}",
+            "requests": 2,
+            "last_used_at": token["last_used_at"]
+        }]));
+
+        // Everything was created just now, so only tokens unused since a future point are inactive
+        usage.flush(&mut *db).unwrap();
+        let req = test::TestRequest::get()
+            .uri(&format!("/tokens/inactive?since={}", Utc::now().timestamp() + 60))
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let inactive: Value = test::read_response_json(&mut app, req);
+        let ids: Vec<i64> = inactive.as_array().unwrap().iter().map(|t| t["id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let req = test::TestRequest::get()
+            .uri("/tokens/inactive")
+            .header("Authorization", bearer(&root))
+            .to_request();
+        let inactive: Value = test::read_response_json(&mut app, req);
+        assert_eq!(inactive, json!([]));
+    }
+}
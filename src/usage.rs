@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+
+use crate::database::{Error, Storage, TokenUsage};
+use crate::utils;
+
+/// Collects token usage in memory, so authenticating a request doesn't cost a database write.
+/// `jobs::spawn_usage_flusher` periodically writes it to the database.
+#[derive(Clone, Default)]
+pub struct UsageTracker {
+    pending: Arc<Mutex<HashMap<(i32, String), TokenUsage>>>,
+}
+
+impl UsageTracker {
+    pub fn record(&self, token_id: i32, endpoint: String) {
+        let now = Utc::now().naive_utc();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let usage = pending
+            .entry((token_id, endpoint.clone()))
+            .or_insert_with(|| TokenUsage { token_id, endpoint, requests: 0, last_used_at: now });
+        usage.requests += 1;
+        usage.last_used_at = now;
+    }
+
+    /// Writes everything collected so far to the database and returns the number of entries.
+    /// If that fails the usage is kept for the next attempt.
+    pub fn flush(&self, db: &mut dyn Storage) -> Result<usize, Error> {
+        let usage: Vec<TokenUsage> = {
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            pending.drain().map(|(_, usage)| usage).collect()
+        };
+        if usage.is_empty() {
+            return Ok(0);
+        }
+        if let Err(e) = db.record_token_usage(&usage) {
+            debug!(utils::LOGGER, "Keeping token usage for the next flush"; "entries" => usage.len());
+            let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+            for entry in usage {
+                let recorded = pending
+                    .entry((entry.token_id, entry.endpoint.clone()))
+                    .or_insert_with(|| TokenUsage { requests: 0, ..entry.clone() });
+                recorded.requests += entry.requests;
+                recorded.last_used_at = recorded.last_used_at.max(entry.last_used_at);
+            }
+            return Err(e);
+        }
+        Ok(usage.len())
+    }
+}
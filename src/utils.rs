@@ -1,21 +1,15 @@
 use actix_web::HttpRequest;
 use lazy_static::lazy_static;
-use slog::{Drain, Logger};
-use slog_async;
-use slog_term;
+use sha2::{Digest, Sha256};
+use slog::Logger;
 
 use crate::errors::UserError;
-
-fn logger() -> Logger {
-    let decorator = slog_term::TermDecorator::new().force_color().build();
-    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
-    let drain = slog_async::Async::new(drain).build().fuse();
-
-    Logger::root(drain, o!())
-}
+use crate::logging;
+use crate::routes;
+use crate::settings;
 
 lazy_static! {
-    pub static ref LOGGER: Logger = logger();
+    pub static ref LOGGER: Logger = logging::logger(&settings::ENV.logging);
 }
 
 pub fn get_auth_token(req: &HttpRequest) -> Result<String, UserError> {
@@ -30,3 +24,19 @@ pub fn get_auth_token(req: &HttpRequest) -> Result<String, UserError> {
     let _token: Vec<&str> = token_header.split_ascii_whitespace().collect();
     Ok(_token.get(1).ok_or(UserError::BadRequest("could not find token. is it prefixed with `Bearer` ?"))?.to_string())
 }
+
+/// Tokens are only stored as the hex encoded SHA-256 hash of their secret.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Path of the resource the request was routed to, e.g. `/banlist/{id}`, so requests for different
+/// IDs are counted as the same route. Never the path the client sent, which it can vary freely.
+pub fn route(req: &HttpRequest) -> &'static str {
+    routes::resource(req).unwrap_or("unmatched")
+}
+
+/// Method and route of the request, e.g. `GET /banlist/{id}`.
+pub fn endpoint(req: &HttpRequest) -> String {
+    format!("{} {}", req.method(), route(req))
+}
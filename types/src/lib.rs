@@ -0,0 +1,366 @@
+//! Request and response bodies of the SpamWatch API.
+//!
+//! The server builds its responses from these types, so clients using them can't drift from it.
+//! Points in time are Unix timestamps.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "postgres")]
+use postgres_types::{FromSql, ToSql};
+use serde::{Deserialize, Serialize};
+
+//region Tokens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "postgres", derive(ToSql, FromSql), postgres(name = "permission"))]
+pub enum Permission {
+    // Can read from the API
+    User,
+    // Can add IDs to the API
+    Admin,
+    // Can create/revoke tokens
+    Root,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    pub id: i32,
+    pub permission: Permission,
+    pub userid: i64,
+    pub retired: bool,
+    pub expires_at: Option<i64>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Unknown for tokens created before this was recorded
+    pub created_at: Option<i64>,
+    /// ID of the token that created this one, `None` for the genesis token
+    pub created_by: Option<i32>,
+    /// Lags behind by up to `usage_flush_interval` seconds
+    pub last_used_at: Option<i64>,
+}
+
+/// Response to creating a token, the only one that contains its secret.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreatedToken {
+    #[serde(flatten)]
+    pub token: Token,
+    #[serde(rename = "token")]
+    pub secret: String,
+}
+
+/// A token and the endpoints it used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenDetails {
+    #[serde(flatten)]
+    pub token: Token,
+    pub usage: Vec<TokenUsage>,
+}
+
+/// Requests a token made to a single endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    /// Method and route, e.g. `GET /banlist/{id}`
+    pub endpoint: String,
+    pub requests: i64,
+    pub last_used_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateToken {
+    /// Telegram ID of the user the token is for
+    pub id: i64,
+    pub permission: Permission,
+    /// Defaults to the lifetime configured for the permission
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl CreateToken {
+    pub fn new(id: i64, permission: Permission) -> Self {
+        CreateToken {
+            id,
+            permission,
+            expires_at: None,
+            name: None,
+            description: None,
+        }
+    }
+}
+//endregion
+
+//region Rate limits
+/// Token bucket: `burst` requests at once, refilled completely within `period` seconds.
+/// A `burst` of 0 disables the limit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Limit {
+    pub burst: u32,
+    pub period: u64,
+}
+
+impl Limit {
+    pub fn unlimited(&self) -> bool {
+        self.burst == 0 || self.period == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppliedLimit {
+    #[serde(flatten)]
+    pub limit: Limit,
+    /// Set for this token instead of coming from the config
+    pub overridden: bool,
+}
+
+/// Bucket of a single endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    pub endpoint: String,
+    /// Requests left, including fractions of requests refilled since `updated_at`
+    pub available: f64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLimits {
+    /// By limit name
+    pub limits: BTreeMap<String, AppliedLimit>,
+    pub buckets: Vec<Bucket>,
+}
+//endregion
+
+//region Banlist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    pub id: i64,
+    pub reason: String,
+    pub date: i64,
+    /// ID of the token that added the ban
+    pub admin: i32,
+    pub message: Option<String>,
+    pub expires: Option<i64>,
+    /// ID of a `BanCategory`. `reason` is free-text detail then, and may be empty
+    #[serde(default)]
+    pub category: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateBan {
+    pub id: i64,
+    /// Can only be left out or empty when `category` is given
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Permanent if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<i32>,
+}
+
+impl CreateBan {
+    pub fn new(id: i64, reason: impl Into<String>) -> Self {
+        CreateBan {
+            id,
+            reason: reason.into(),
+            message: None,
+            expires: None,
+            category: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanStatus {
+    Created,
+    Updated,
+    /// The ban was invalid and skipped, `error` says why
+    Error,
+}
+
+/// Outcome of a single ban of `POST /banlist?partial=true`, in the order they were sent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanResult {
+    pub id: i64,
+    pub status: BanStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A managed ban reason. Bots can act on bans of the categories they trust.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanCategory {
+    pub id: i32,
+    /// Unique, made of lowercase letters, digits, `-` and `_`
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Body of creating or replacing a ban category.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreateBanCategory {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanSort {
+    Id,
+    Date,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Query parameters of `GET /banlist`. With any of them set, a page of bans is returned
+/// instead of the whole banlist.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BanListQuery {
+    /// Bans per page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    /// `cursor` of the previous page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Only bans from this point in time on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<i64>,
+    /// Only bans from before this point in time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+    /// Only bans added by this token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<i32>,
+    /// Only bans whose reason contains this, ignoring case
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Only bans with or without a message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_message: Option<bool>,
+    /// Only bans of this category
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<i32>,
+    /// Defaults to `id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<BanSort>,
+    /// Defaults to `asc`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanPage {
+    pub bans: Vec<Ban>,
+    /// Pass this as `after` to get the next page, `None` on the last page
+    pub cursor: Option<String>,
+    /// Path of the next page with the same filters
+    pub next: Option<String>,
+}
+
+/// A ban found by a full-text search.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanMatch {
+    #[serde(flatten)]
+    pub ban: Ban,
+    /// Relevance of the match, higher is better
+    pub rank: f32,
+    /// The reason as HTML, with matching words in `<mark>` tags
+    pub reason_snippet: String,
+    /// The parts of the message that match best, like `reason_snippet`
+    pub message_snippet: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "postgres", derive(ToSql, FromSql), postgres(name = "ban_action"))]
+pub enum BanAction {
+    Create,
+    Update,
+    Delete,
+    Expire,
+}
+
+/// A single change to a ban.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanHistory {
+    pub id: i32,
+    pub ban_id: i64,
+    pub action: BanAction,
+    pub token: Option<i32>,
+    pub old_reason: Option<String>,
+    pub new_reason: Option<String>,
+    pub old_message: Option<String>,
+    pub new_message: Option<String>,
+    pub date: i64,
+}
+
+/// IDs banned and unbanned since a point in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanChanges {
+    pub added: Vec<i64>,
+    pub removed: Vec<i64>,
+    /// Pass this to get the changes after these. Changes close to it may be returned again
+    pub cursor: String,
+}
+//endregion
+
+//region Root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+    pub version: String,
+    pub major: String,
+    pub minor: String,
+    pub patch: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub total_ban_count: i64,
+    /// Every category, uncategorized bans aren't counted here
+    pub categories: Vec<CategoryStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CategoryStats {
+    pub id: i32,
+    pub name: String,
+    pub ban_count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ready {
+    /// `ready` or `unavailable`
+    pub status: String,
+    /// `ok` or the reason the check failed, by check
+    pub checks: BTreeMap<String, String>,
+}
+//endregion
+
+/// Body of every error response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Error {
+    /// HTTP status code
+    pub code: u16,
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// When the next request is allowed, for `429 Too Many Requests`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}